@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::{ErrorKind, Errors};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CircuitState {
+    /// Calls go through; consecutive failures are counted.
+    Closed,
+    /// Calls are rejected without touching the dependency until the cool down has passed.
+    Open,
+    /// A single trial call is let through to find out whether the dependency is back.
+    HalfOpen,
+}
+
+impl std::fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+/// Stops hammering a dependency that keeps failing. Timed by `IO::now`, so the cool down
+/// elapses in virtual time under simulation.
+///
+/// Callers check `allow` before each call and report the outcome with `record`.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    state: CircuitState,
+    failure_threshold: u32,
+    consecutive_failures: u32,
+    open_duration: Duration,
+    opened_at: Duration,
+    /// Whether the half-open trial call has been let through and its outcome not recorded.
+    trial_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    /// Opens after 5 consecutive failures and tries again after 1s.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            state: CircuitState::Closed,
+            failure_threshold: 5,
+            consecutive_failures: 0,
+            open_duration: Duration::from_secs(1),
+            opened_at: Duration::ZERO,
+            trial_in_flight: false,
+        }
+    }
+
+    pub fn with_failure_threshold(mut self, failure_threshold: u32) -> Self {
+        self.failure_threshold = failure_threshold.max(1);
+        self
+    }
+
+    /// How long the breaker stays open before letting a trial call through.
+    pub fn with_open_duration(mut self, open_duration: Duration) -> Self {
+        self.open_duration = open_duration;
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    /// Returns an error if the call should not be attempted right now. While half-open only
+    /// the trial call gets through, until its outcome is recorded.
+    pub fn allow(&mut self, now: Duration) -> Result<(), Errors> {
+        self.update(now);
+        match self.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::HalfOpen if !self.trial_in_flight => {
+                self.trial_in_flight = true;
+                Ok(())
+            }
+            CircuitState::HalfOpen => Err(Errors::new(ErrorKind::CircuitOpen)
+                .with_operation(self.name)
+                .with_source("waiting on the trial call")),
+            CircuitState::Open => Err(Errors::new(ErrorKind::CircuitOpen)
+                .with_operation(self.name)
+                .with_source(format!(
+                    "retrying in {:?}",
+                    self.open_duration - now.saturating_sub(self.opened_at)
+                ))),
+        }
+    }
+
+    /// Moves an open breaker to half-open once its cool down has passed, so its state shows
+    /// that before the trial call is made. `allow` does the same on its own.
+    pub fn update(&mut self, now: Duration) {
+        if self.state == CircuitState::Open
+            && now.saturating_sub(self.opened_at) >= self.open_duration
+        {
+            self.transition(CircuitState::HalfOpen);
+        }
+    }
+
+    /// Feeds the outcome of a call that `allow` let through back into the breaker. Permanent
+    /// errors say nothing about whether the dependency is reachable and are ignored.
+    pub fn record<T>(&mut self, now: Duration, result: &Result<T, Errors>) {
+        self.trial_in_flight = false;
+        match result {
+            Err(err) if !err.is_transient() => {}
+            Ok(_) => {
+                self.consecutive_failures = 0;
+                if self.state != CircuitState::Closed {
+                    self.transition(CircuitState::Closed);
+                }
+            }
+            Err(_) => {
+                self.consecutive_failures += 1;
+                let should_open = match self.state {
+                    CircuitState::HalfOpen => true,
+                    CircuitState::Closed => self.consecutive_failures >= self.failure_threshold,
+                    CircuitState::Open => false,
+                };
+                if should_open {
+                    self.opened_at = now;
+                    self.transition(CircuitState::Open);
+                }
+            }
+        }
+    }
+
+    fn transition(&mut self, to: CircuitState) {
+        match to {
+            CircuitState::Open => warn!(
+                "{} circuit breaker {} -> {} after {} consecutive failures",
+                self.name, self.state, to, self.consecutive_failures
+            ),
+            _ => info!("{} circuit breaker {} -> {}", self.name, self.state, to),
+        }
+        self.state = to;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorClass;
+
+    fn failed() -> Result<(), Errors> {
+        Err(Errors::new(ErrorKind::RedisConnectionError))
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new("redis")
+            .with_failure_threshold(3)
+            .with_open_duration(secs(10))
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_only() {
+        let mut breaker = breaker();
+        breaker.record(secs(0), &failed());
+        breaker.record(secs(0), &failed());
+        breaker.record(secs(0), &Ok(()));
+        breaker.record(secs(0), &failed());
+        breaker.record(secs(0), &failed());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record(secs(1), &failed());
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert_eq!(breaker.allow(secs(2)).unwrap_err(), ErrorKind::CircuitOpen);
+    }
+
+    #[test]
+    fn ignores_permanent_errors() {
+        let mut breaker = breaker();
+        let permanent: Result<(), Errors> =
+            Err(Errors::new(ErrorKind::RedisKeyRetrievalError).with_class(ErrorClass::Permanent));
+        for _ in 0..10 {
+            breaker.record(secs(0), &permanent);
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn goes_half_open_after_the_cool_down() {
+        let mut breaker = breaker();
+        for _ in 0..3 {
+            breaker.record(secs(5), &failed());
+        }
+        breaker.update(secs(14));
+        assert_eq!(breaker.state(), CircuitState::Open);
+        breaker.update(secs(15));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.allow(secs(15)).is_ok());
+    }
+
+    #[test]
+    fn allow_lets_the_trial_call_through_on_its_own() {
+        let mut breaker = breaker();
+        for _ in 0..3 {
+            breaker.record(secs(0), &failed());
+        }
+        assert!(breaker.allow(secs(10)).is_ok());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn only_one_trial_call_at_a_time() {
+        let mut breaker = breaker();
+        for _ in 0..3 {
+            breaker.record(secs(0), &failed());
+        }
+        assert!(breaker.allow(secs(10)).is_ok());
+        assert_eq!(breaker.allow(secs(10)).unwrap_err(), ErrorKind::CircuitOpen);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        //  A permanent error says nothing about the dependency, so another trial may go
+        let permanent: Result<(), Errors> =
+            Err(Errors::new(ErrorKind::RedisKeyRetrievalError).with_class(ErrorClass::Permanent));
+        breaker.record(secs(10), &permanent);
+        assert!(breaker.allow(secs(10)).is_ok());
+        breaker.record(secs(10), &Ok(()));
+        assert!(breaker.allow(secs(10)).is_ok());
+        assert!(breaker.allow(secs(10)).is_ok());
+    }
+
+    #[test]
+    fn trial_call_closes_or_reopens() {
+        let mut breaker = breaker();
+        for _ in 0..3 {
+            breaker.record(secs(0), &failed());
+        }
+        breaker.update(secs(10));
+        breaker.record(secs(10), &failed());
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.allow(secs(19)).is_err());
+
+        breaker.update(secs(20));
+        breaker.record(secs(20), &Ok(()));
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        breaker.record(secs(20), &failed());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
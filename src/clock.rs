@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait Clock {
+    async fn sleep(&mut self, duration: Duration);
+    /// Time elapsed since the clock was created.
+    fn now(&self) -> Duration;
+}
+
+pub struct RealClock {
+    started_at: Instant,
+}
+
+impl RealClock {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+        }
+    }
+}
+
+impl Default for RealClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Clock for RealClock {
+    async fn sleep(&mut self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+
+    fn now(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+}
+
+#[derive(Default)]
+pub struct SimulatedClock {
+    current_time: Duration,
+}
+
+impl SimulatedClock {
+    pub fn new() -> Self {
+        Self {
+            current_time: Duration::ZERO,
+        }
+    }
+
+    pub fn advance(&mut self, duration: Duration) {
+        self.current_time += duration;
+    }
+}
+
+#[async_trait]
+impl Clock for SimulatedClock {
+    async fn sleep(&mut self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    fn now(&self) -> Duration {
+        self.current_time
+    }
+}
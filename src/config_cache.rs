@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// What the cache knows about a key at a given time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CachedConfig {
+    /// Younger than the TTL, serve it without asking Redis.
+    Fresh(String),
+    /// Past the TTL but within the staleness window. Serve this while the refresh catches up.
+    Stale { value: String, age: Duration },
+    /// Never fetched, or too old to be trusted.
+    Missing,
+}
+
+struct Entry {
+    value: String,
+    fetched_at: Duration,
+    /// When the value should next be fetched again.
+    refresh_at: Duration,
+}
+
+/// Last known good config values, timestamped with `IO::now` so entries age in virtual
+/// time under simulation. Each entry is due for a refresh once it reaches the TTL, and
+/// again every `refresh_retry` for as long as refreshing fails.
+pub struct ConfigCache {
+    ttl: Duration,
+    max_staleness: Duration,
+    refresh_retry: Duration,
+    entries: HashMap<String, Entry>,
+}
+
+impl Default for ConfigCache {
+    /// Values are fresh for 5s and may be served stale for another 60s.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(5), Duration::from_secs(60))
+    }
+}
+
+impl ConfigCache {
+    /// `ttl` is how long a value is served without refreshing, `max_staleness` how much longer
+    /// it may still be served while refreshes fail.
+    pub fn new(ttl: Duration, max_staleness: Duration) -> Self {
+        Self {
+            ttl,
+            max_staleness,
+            refresh_retry: Duration::from_secs(1),
+            entries: HashMap::new(),
+        }
+    }
+
+    /// How long to wait before trying again after a refresh fails. 1s by default.
+    pub fn with_refresh_retry(mut self, interval: Duration) -> Self {
+        self.refresh_retry = interval;
+        self
+    }
+
+    /// A cache that never serves anything, so every lookup goes to Redis.
+    pub fn disabled() -> Self {
+        Self::new(Duration::ZERO, Duration::ZERO)
+    }
+
+    pub fn get(&self, key: &str, now: Duration) -> CachedConfig {
+        let Some(entry) = self.entries.get(key) else {
+            return CachedConfig::Missing;
+        };
+        let age = now.saturating_sub(entry.fetched_at);
+        if age < self.ttl {
+            CachedConfig::Fresh(entry.value.clone())
+        } else if age < self.ttl + self.max_staleness {
+            CachedConfig::Stale {
+                value: entry.value.clone(),
+                age,
+            }
+        } else {
+            CachedConfig::Missing
+        }
+    }
+
+    /// Whether `key` should be refreshed at `now`. Values past the staleness window are
+    /// never due, since they are no longer served and have to be fetched on demand.
+    pub fn refresh_due(&self, key: &str, now: Duration) -> bool {
+        self.entries.get(key).is_some_and(|entry| {
+            now >= entry.refresh_at
+                && now.saturating_sub(entry.fetched_at) < self.ttl + self.max_staleness
+        })
+    }
+
+    /// Puts the next refresh of `key` off by the retry interval.
+    pub fn refresh_failed(&mut self, key: &str, now: Duration) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.refresh_at = now + self.refresh_retry;
+        }
+    }
+
+    pub fn insert(&mut self, key: &str, value: String, now: Duration) {
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                fetched_at: now,
+                refresh_at: now + self.ttl,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn cache() -> ConfigCache {
+        let mut cache = ConfigCache::new(secs(5), secs(10)).with_refresh_retry(secs(2));
+        cache.insert("key", "value".to_string(), secs(100));
+        cache
+    }
+
+    #[test]
+    fn ages_from_fresh_to_stale_to_missing() {
+        let cache = cache();
+        assert_eq!(cache.get("other", secs(100)), CachedConfig::Missing);
+        assert_eq!(
+            cache.get("key", secs(104)),
+            CachedConfig::Fresh("value".to_string())
+        );
+        assert_eq!(
+            cache.get("key", secs(105)),
+            CachedConfig::Stale {
+                value: "value".to_string(),
+                age: secs(5)
+            }
+        );
+        assert_eq!(cache.get("key", secs(115)), CachedConfig::Missing);
+    }
+
+    #[test]
+    fn refresh_is_due_once_the_value_reaches_the_ttl() {
+        let cache = cache();
+        assert!(!cache.refresh_due("key", secs(104)));
+        assert!(cache.refresh_due("key", secs(105)));
+        assert!(!cache.refresh_due("key", secs(115)));
+        assert!(!cache.refresh_due("other", secs(105)));
+    }
+
+    #[test]
+    fn failed_refreshes_back_off_and_keep_serving_stale() {
+        let mut cache = cache();
+        cache.refresh_failed("key", secs(106));
+        assert!(!cache.refresh_due("key", secs(107)));
+        assert!(cache.refresh_due("key", secs(108)));
+        assert!(matches!(
+            cache.get("key", secs(108)),
+            CachedConfig::Stale { .. }
+        ));
+
+        cache.insert("key", "refreshed".to_string(), secs(108));
+        assert!(!cache.refresh_due("key", secs(112)));
+        assert_eq!(
+            cache.get("key", secs(112)),
+            CachedConfig::Fresh("refreshed".to_string())
+        );
+    }
+
+    #[test]
+    fn disabled_cache_serves_nothing() {
+        let mut cache = ConfigCache::disabled();
+        cache.insert("key", "value".to_string(), secs(0));
+        assert_eq!(cache.get("key", secs(0)), CachedConfig::Missing);
+        assert!(!cache.refresh_due("key", secs(0)));
+    }
+}
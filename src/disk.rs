@@ -0,0 +1,66 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{ErrorKind, Errors};
+
+#[derive(Debug)]
+struct DiskState {
+    capacity: usize,
+    used: usize,
+}
+
+/// The capacity every simulated file draws from. Clones share the same disk, so one file
+/// filling it up makes writes to all of them fail until space is freed again.
+#[derive(Clone, Debug)]
+pub struct SimulatedDisk {
+    state: Arc<Mutex<DiskState>>,
+}
+
+impl Default for SimulatedDisk {
+    /// A 100MB disk.
+    fn default() -> Self {
+        Self::new(100_000_000)
+    }
+}
+
+impl SimulatedDisk {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(DiskState { capacity, used: 0 })),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.state.lock().unwrap().capacity
+    }
+
+    pub fn used(&self) -> usize {
+        self.state.lock().unwrap().used
+    }
+
+    /// Resizes the disk. Shrinking it below what is used just makes the next writes fail.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.state.lock().unwrap().capacity = capacity;
+    }
+
+    /// Claims `bytes`, or fails with `DiskFull` and claims nothing if they don't fit.
+    pub fn allocate(&self, bytes: usize) -> Result<(), Errors> {
+        let mut state = self.state.lock().unwrap();
+        if state.used + bytes > state.capacity {
+            return Err(Errors::new(ErrorKind::DiskFull)
+                .with_operation("write")
+                .with_source(format!(
+                    "{} bytes needed, {} of {} free",
+                    bytes,
+                    state.capacity.saturating_sub(state.used),
+                    state.capacity
+                )));
+        }
+        state.used += bytes;
+        Ok(())
+    }
+
+    pub fn free(&self, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.used = state.used.saturating_sub(bytes);
+    }
+}
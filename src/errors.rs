@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+/// What went wrong, independent of where or why.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    KafkaConnectionError,
+    NoKafkaMessage,
+    InvalidKafkaMessage,
+    KafkaCommitError,
+    RedisConnectionError,
+    RedisKeyRetrievalError,
+    FileOpenError,
+    FileReadError,
+    ExpectedFileReadError,
+    FileWriteError,
+    FileSyncError,
+    DiskFull,
+    CircuitOpen,
+    ShutdownIncomplete,
+}
+
+impl ErrorKind {
+    /// The classification used when the underlying source doesn't tell us any better.
+    fn default_class(&self) -> ErrorClass {
+        match self {
+            ErrorKind::InvalidKafkaMessage
+            | ErrorKind::ExpectedFileReadError
+            | ErrorKind::ShutdownIncomplete => ErrorClass::Permanent,
+            _ => ErrorClass::Transient,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::KafkaConnectionError => write!(f, "Kafka connection error"),
+            ErrorKind::NoKafkaMessage => write!(f, "No Kafka message"),
+            ErrorKind::InvalidKafkaMessage => write!(f, "Invalid format of Kafka message"),
+            ErrorKind::KafkaCommitError => write!(f, "Failed to commit Kafka offset"),
+            ErrorKind::RedisConnectionError => write!(f, "Redis connection error"),
+            ErrorKind::RedisKeyRetrievalError => write!(f, "Error retrieving redis key"),
+            ErrorKind::FileOpenError => write!(f, "Failed to open file"),
+            ErrorKind::FileReadError => write!(f, "Failed to read from file"),
+            ErrorKind::ExpectedFileReadError => write!(f, "Expected file read error"),
+            ErrorKind::FileWriteError => write!(f, "Failed to write to file"),
+            ErrorKind::FileSyncError => write!(f, "Failed to sync file"),
+            ErrorKind::DiskFull => write!(f, "No space left on disk"),
+            ErrorKind::CircuitOpen => write!(f, "Circuit breaker open"),
+            ErrorKind::ShutdownIncomplete => write!(f, "Records left unwritten at shutdown"),
+        }
+    }
+}
+
+/// Whether retrying the failed operation could possibly succeed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    Transient,
+    Permanent,
+}
+
+type Source = Arc<dyn std::error::Error + Send + Sync + 'static>;
+
+/// An error raised by an IO operation, carrying enough context to decide whether to retry
+/// and to explain in the logs why it happened.
+#[derive(Clone, Debug)]
+pub struct Errors {
+    kind: ErrorKind,
+    class: ErrorClass,
+    operation: Option<&'static str>,
+    attempt: Option<u32>,
+    source: Option<Source>,
+}
+
+impl Errors {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            class: kind.default_class(),
+            operation: None,
+            attempt: None,
+            source: None,
+        }
+    }
+
+    /// Names the IO operation that failed, e.g. `"get_redis_config"`.
+    pub fn with_operation(mut self, operation: &'static str) -> Self {
+        self.operation = Some(operation);
+        self
+    }
+
+    /// Records which attempt (starting at 1) produced this error.
+    pub fn with_attempt(mut self, attempt: u32) -> Self {
+        self.attempt = Some(attempt);
+        self
+    }
+
+    pub fn with_class(mut self, class: ErrorClass) -> Self {
+        self.class = class;
+        self
+    }
+
+    pub fn with_source(
+        mut self,
+        source: impl Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+    ) -> Self {
+        self.source = Some(Arc::from(source.into()));
+        self
+    }
+
+    /// Attaches an IO error, classifying it as permanent when retrying can't help.
+    pub fn with_io_source(self, source: std::io::Error) -> Self {
+        let class = match source.kind() {
+            std::io::ErrorKind::NotFound
+            | std::io::ErrorKind::PermissionDenied
+            | std::io::ErrorKind::InvalidInput
+            | std::io::ErrorKind::Unsupported => ErrorClass::Permanent,
+            _ => self.class,
+        };
+        self.with_class(class).with_source(source)
+    }
+
+    /// Attaches a Redis error, treating anything that isn't a connectivity problem as permanent.
+    pub fn with_redis_source(self, source: redis::RedisError) -> Self {
+        let class = if source.is_io_error()
+            || source.is_timeout()
+            || source.is_connection_dropped()
+            || source.is_connection_refusal()
+        {
+            ErrorClass::Transient
+        } else {
+            ErrorClass::Permanent
+        };
+        self.with_class(class).with_source(source)
+    }
+
+    /// Attaches a Kafka error. Client configuration mistakes never fix themselves.
+    pub fn with_kafka_source(self, source: rdkafka::error::KafkaError) -> Self {
+        let class = match source {
+            rdkafka::error::KafkaError::ClientConfig(..)
+            | rdkafka::error::KafkaError::ClientCreation(_) => ErrorClass::Permanent,
+            _ => self.class,
+        };
+        self.with_class(class).with_source(source)
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        self.class
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.class == ErrorClass::Transient
+    }
+
+    pub fn operation(&self) -> Option<&'static str> {
+        self.operation
+    }
+
+    pub fn attempt(&self) -> Option<u32> {
+        self.attempt
+    }
+}
+
+impl From<ErrorKind> for Errors {
+    fn from(kind: ErrorKind) -> Self {
+        Errors::new(kind)
+    }
+}
+
+impl PartialEq<ErrorKind> for Errors {
+    fn eq(&self, other: &ErrorKind) -> bool {
+        self.kind == *other
+    }
+}
+
+impl std::fmt::Display for Errors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(operation) = self.operation {
+            write!(f, " in {}", operation)?;
+        }
+        if let Some(attempt) = self.attempt {
+            write!(f, " (attempt {})", attempt)?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Errors {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
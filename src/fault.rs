@@ -0,0 +1,35 @@
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub enum FaultType {
+    KafkaConnectionFailure,
+    KafkaReadFailure,
+    RedisConnectionFailure,
+    RedisReadFailure,
+    FileOpenFailure,
+    FileFaultType(FileFaultType),
+}
+
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum FileFaultType {
+    FileReadFailure,
+    FileWriteFailure,
+    FileSizeExceededFailure,
+    FileMetadataSyncFailure,
+}
+
+impl FaultType {
+    /// Every fault the simulator knows how to inject.
+    pub fn all() -> Vec<FaultType> {
+        vec![
+            FaultType::KafkaConnectionFailure,
+            FaultType::KafkaReadFailure,
+            FaultType::RedisConnectionFailure,
+            FaultType::RedisReadFailure,
+            FaultType::FileOpenFailure,
+            FaultType::FileFaultType(FileFaultType::FileReadFailure),
+            FaultType::FileFaultType(FileFaultType::FileWriteFailure),
+            FaultType::FileFaultType(FileFaultType::FileSizeExceededFailure),
+            FaultType::FileFaultType(FileFaultType::FileMetadataSyncFailure),
+        ]
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::{trace, warn};
+
+use crate::filesystem::Inode;
+use crate::{frame, ErrorClass, ErrorKind, Errors, FileFaultType, SimulatedFileSystem};
+
+/// An append-only log of records. Every `write` appends one framed, checksummed record (see
+/// `frame`), and readers only ever see records whose checksum holds.
+#[async_trait]
+pub trait File {
+    async fn read(&mut self, size: usize) -> Result<Vec<u8>, Errors>;
+    async fn write(&mut self, data: &str) -> Result<usize, Errors>;
+    /// Appends one record per entry of `data` with a single write. Returns the payload bytes
+    /// written.
+    async fn write_batch(&mut self, data: &[String]) -> Result<usize, Errors>;
+    async fn fsync(&mut self) -> Result<(), Errors>;
+    async fn read_last_n_entries(&mut self, n: usize) -> Result<Vec<String>, Errors>;
+    /// Scans the whole file and truncates anything after the last valid record, e.g. a write
+    /// torn by a crash. Returns how many bytes were cut off.
+    async fn recover(&mut self) -> Result<usize, Errors>;
+    /// Bytes in the file, framing included.
+    fn size(&self) -> usize;
+    /// Syncs the file and stops accepting writes. Its records can still be read.
+    async fn close(&mut self) -> Result<(), Errors>;
+    /// Deletes the file, giving its space back.
+    async fn remove(&mut self) -> Result<(), Errors>;
+}
+
+/// Running out of space gets its own kind, so callers can stop producing instead of retrying.
+fn write_error(operation: &'static str, e: std::io::Error) -> Errors {
+    let kind = match e.kind() {
+        std::io::ErrorKind::StorageFull => ErrorKind::DiskFull,
+        _ => ErrorKind::FileWriteError,
+    };
+    Errors::new(kind)
+        .with_operation(operation)
+        .with_io_source(e)
+}
+
+/// Frames every record of a batch into one buffer, returning the offset of each frame
+/// within it.
+fn encode_batch(data: &[String]) -> (Vec<u8>, Vec<usize>) {
+    let mut buffer = Vec::new();
+    let mut offsets = Vec::with_capacity(data.len());
+    for record in data {
+        offsets.push(buffer.len());
+        buffer.extend_from_slice(&frame::encode(record.as_bytes()));
+    }
+    (buffer, offsets)
+}
+
+fn decode_entries(records: Vec<(usize, Vec<u8>)>) -> Vec<String> {
+    records
+        .into_iter()
+        .map(|(_, payload)| String::from_utf8_lossy(&payload).into_owned())
+        .collect()
+}
+
+pub struct RealFile {
+    path: PathBuf,
+    file: Option<tokio::fs::File>,
+    /// Byte offset of every record in the file, so the tail can be read without a full scan.
+    record_offsets: Vec<u64>,
+    len: u64,
+    /// Part of a failed write may still be on disk after `len`.
+    torn: bool,
+}
+
+impl RealFile {
+    pub fn new(path: &Path, file: tokio::fs::File) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            file: Some(file),
+            record_offsets: Vec::new(),
+            len: 0,
+            torn: false,
+        }
+    }
+
+    /// Opens `path` for appending, creating it if needed.
+    pub async fn open(path: &Path) -> Result<Self, Errors> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .append(true)
+            .open(path)
+            .await
+            .map_err(|e| {
+                Errors::new(ErrorKind::FileOpenError)
+                    .with_operation("open_file")
+                    .with_io_source(e)
+            })?;
+        Ok(Self::new(path, file))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads from `start` to the end of the file. A closed file is reopened just for the read.
+    async fn read_from(&mut self, start: u64, operation: &'static str) -> Result<Vec<u8>, Errors> {
+        let read_error = |e: std::io::Error| {
+            Errors::new(ErrorKind::FileReadError)
+                .with_operation(operation)
+                .with_io_source(e)
+        };
+        let mut reopened;
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => {
+                reopened = tokio::fs::File::open(&self.path)
+                    .await
+                    .map_err(read_error)?;
+                &mut reopened
+            }
+        };
+        file.seek(SeekFrom::Start(start))
+            .await
+            .map_err(read_error)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).await.map_err(read_error)?;
+        Ok(buffer)
+    }
+
+    /// Cuts off whatever part of a failed write reached the file. Left in place, the next
+    /// append would land after it and `recover` would drop that record with the torn one.
+    async fn discard_partial_write(&mut self) -> Result<(), Errors> {
+        let len = self.len;
+        self.file("write")?
+            .set_len(len)
+            .await
+            .map_err(|e| write_error("write", e))?;
+        self.torn = false;
+        Ok(())
+    }
+
+    fn file(&mut self, operation: &'static str) -> Result<&mut tokio::fs::File, Errors> {
+        self.file.as_mut().ok_or_else(|| {
+            Errors::new(ErrorKind::FileReadError)
+                .with_operation(operation)
+                .with_class(ErrorClass::Permanent)
+                .with_source("file is not open")
+        })
+    }
+}
+
+#[async_trait]
+impl File for RealFile {
+    async fn read(&mut self, size: usize) -> Result<Vec<u8>, Errors> {
+        let mut buffer = vec![0; size];
+        self.file("read")?.read(&mut buffer).await.map_err(|e| {
+            Errors::new(ErrorKind::FileReadError)
+                .with_operation("read")
+                .with_io_source(e)
+        })?;
+        Ok(buffer)
+    }
+
+    async fn write(&mut self, data: &str) -> Result<usize, Errors> {
+        self.write_batch(&[data.to_string()]).await
+    }
+
+    async fn write_batch(&mut self, data: &[String]) -> Result<usize, Errors> {
+        if self.torn {
+            self.discard_partial_write().await?;
+        }
+        let (buffer, offsets) = encode_batch(data);
+        let file = self.file("write")?;
+        //  tokio finishes writes on a background thread, so only the flush reports how it went
+        let written = match file.write_all(&buffer).await {
+            Ok(()) => file.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            self.torn = true;
+            if let Err(truncate_error) = self.discard_partial_write().await {
+                warn!(
+                    "failed to cut off a partial write, retrying before the next one: {}",
+                    truncate_error
+                );
+            }
+            return Err(write_error("write", e));
+        }
+        let start = self.len;
+        self.record_offsets
+            .extend(offsets.into_iter().map(|offset| start + offset as u64));
+        self.len += buffer.len() as u64;
+        Ok(data.iter().map(String::len).sum())
+    }
+
+    async fn fsync(&mut self) -> Result<(), Errors> {
+        //  A closed file was synced when it was closed
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.sync_all().await.map_err(|e| match e.kind() {
+            std::io::ErrorKind::StorageFull => write_error("fsync", e),
+            _ => Errors::new(ErrorKind::FileSyncError)
+                .with_operation("fsync")
+                .with_io_source(e),
+        })
+    }
+
+    async fn read_last_n_entries(&mut self, n: usize) -> Result<Vec<String>, Errors> {
+        let Some(&start) = self
+            .record_offsets
+            .get(self.record_offsets.len().saturating_sub(n))
+        else {
+            return Ok(Vec::new());
+        };
+        let buffer = self.read_from(start, "read_last_n_entries").await?;
+        Ok(decode_entries(frame::scan(&buffer).records))
+    }
+
+    async fn recover(&mut self) -> Result<usize, Errors> {
+        let buffer = self.read_from(0, "recover").await?;
+        let scan = frame::scan(&buffer);
+        let truncated = buffer.len() - scan.valid_len;
+        if truncated > 0 {
+            warn!(
+                "truncating {} corrupt bytes after {} valid records",
+                truncated,
+                scan.records.len()
+            );
+            let file = self.file("recover")?;
+            file.set_len(scan.valid_len as u64).await.map_err(|e| {
+                Errors::new(ErrorKind::FileWriteError)
+                    .with_operation("recover")
+                    .with_io_source(e)
+            })?;
+        }
+        self.record_offsets = scan
+            .records
+            .iter()
+            .map(|(offset, _)| *offset as u64)
+            .collect();
+        self.len = scan.valid_len as u64;
+        Ok(truncated)
+    }
+
+    fn size(&self) -> usize {
+        self.len as usize
+    }
+
+    async fn close(&mut self) -> Result<(), Errors> {
+        self.fsync().await?;
+        self.file = None;
+        Ok(())
+    }
+
+    async fn remove(&mut self) -> Result<(), Errors> {
+        self.file = None;
+        tokio::fs::remove_file(&self.path).await.map_err(|e| {
+            Errors::new(ErrorKind::FileWriteError)
+                .with_operation("remove")
+                .with_io_source(e)
+        })?;
+        self.record_offsets.clear();
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// A file in a `SimulatedFileSystem`. The contents live in the filesystem, so they outlive
+/// the handle and survive reopening.
+pub struct SimulatedFile {
+    rng: ChaCha8Rng,
+    fs: SimulatedFileSystem,
+    path: PathBuf,
+    inode: Inode,
+    read_position: usize,
+    closed: bool,
+    fault_probabilities: HashMap<FileFaultType, f64>,
+    /// Where injected faults are reported, so the IO that owns the file can record them.
+    fault_log: Option<Arc<Mutex<Vec<FileFaultType>>>>,
+}
+
+impl SimulatedFile {
+    /// Opens `path` in `fs`, creating it if needed.
+    pub fn open(rng: ChaCha8Rng, fs: SimulatedFileSystem, path: &Path) -> Result<Self, Errors> {
+        let inode = fs.open(path, true)?;
+        let fault_probabilities = HashMap::from([
+            (FileFaultType::FileReadFailure, 0.1),
+            (FileFaultType::FileWriteFailure, 0.1),
+            (FileFaultType::FileSizeExceededFailure, 0.1),
+            (FileFaultType::FileMetadataSyncFailure, 0.1),
+        ]);
+        Ok(Self {
+            rng,
+            fs,
+            path: path.to_path_buf(),
+            inode,
+            read_position: 0,
+            closed: false,
+            fault_probabilities,
+            fault_log: None,
+        })
+    }
+
+    /// Reports every fault injected from now on to `fault_log`.
+    pub fn with_fault_log(mut self, fault_log: Arc<Mutex<Vec<FileFaultType>>>) -> Self {
+        self.fault_log = Some(fault_log);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn should_inject_fault(&mut self, fault_type: &FileFaultType) -> bool {
+        let Some(&probability) = self.fault_probabilities.get(fault_type) else {
+            return false;
+        };
+        let inject = self.rng.gen_bool(probability);
+        if let Some(fault_log) = self.fault_log.as_ref().filter(|_| inject) {
+            fault_log.lock().unwrap().push(fault_type.clone());
+        }
+        inject
+    }
+
+    pub fn set_fault_probability(&mut self, fault_type: FileFaultType, probability: f64) {
+        self.fault_probabilities.insert(fault_type, probability);
+    }
+
+    pub fn snapshot(&self) -> FileSnapshot {
+        self.fs
+            .with_contents(self.inode, "snapshot", |contents, synced_contents| {
+                FileSnapshot {
+                    contents: contents.to_vec(),
+                    synced_contents: synced_contents.to_vec(),
+                    read_position: self.read_position,
+                    write_position: contents.len(),
+                }
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Point-in-time view of a simulated file, used to inspect what a crash would lose.
+#[derive(Clone, Debug, Default)]
+pub struct FileSnapshot {
+    pub contents: Vec<u8>,
+    pub synced_contents: Vec<u8>,
+    pub read_position: usize,
+    pub write_position: usize,
+}
+
+#[async_trait]
+impl File for SimulatedFile {
+    async fn read(&mut self, size: usize) -> Result<Vec<u8>, Errors> {
+        if self.should_inject_fault(&FileFaultType::FileReadFailure) {
+            warn!("Injecting fault while reading from file");
+            return Err(Errors::new(ErrorKind::FileReadError)
+                .with_operation("read")
+                .with_source("injected fault"));
+        }
+        let start = self.read_position;
+        let buffer = self
+            .fs
+            .with_contents(self.inode, "read", |contents, _| {
+                contents.get(start..start + size).map(<[u8]>::to_vec)
+            })?
+            .ok_or_else(|| {
+                Errors::new(ErrorKind::FileReadError)
+                    .with_operation("read")
+                    .with_class(ErrorClass::Permanent)
+                    .with_source("read past the end of the file")
+            })?;
+        self.read_position += size;
+        Ok(buffer)
+    }
+
+    async fn write(&mut self, data: &str) -> Result<usize, Errors> {
+        self.write_batch(&[data.to_string()]).await
+    }
+
+    async fn write_batch(&mut self, data: &[String]) -> Result<usize, Errors> {
+        if self.should_inject_fault(&FileFaultType::FileWriteFailure) {
+            warn!("Injecting fault while writing to file");
+            return Err(Errors::new(ErrorKind::FileWriteError)
+                .with_operation("write")
+                .with_source("injected fault"));
+        }
+        trace!("Not injecting fault while writing to file");
+        if self.closed {
+            return Err(Errors::new(ErrorKind::FileWriteError)
+                .with_operation("write")
+                .with_class(ErrorClass::Permanent)
+                .with_source("file is closed"));
+        }
+        let (buffer, _) = encode_batch(data);
+        trace!(
+            "making a write of {} records, {:?} bytes",
+            data.len(),
+            buffer.len()
+        );
+        if self.should_inject_fault(&FileFaultType::FileSizeExceededFailure) {
+            warn!("Injecting disk full fault while writing to file");
+            return Err(Errors::new(ErrorKind::DiskFull)
+                .with_operation("write")
+                .with_source("injected fault"));
+        }
+        self.fs.append(self.inode, &buffer)?;
+        Ok(data.iter().map(String::len).sum())
+    }
+
+    async fn fsync(&mut self) -> Result<(), Errors> {
+        //  TODO: Should we inject failure for fsync? Seems excessive. How do people program around that?
+        self.fs.fsync(self.inode)
+    }
+
+    async fn read_last_n_entries(&mut self, n: usize) -> Result<Vec<String>, Errors> {
+        let mut records =
+            self.fs
+                .with_contents(self.inode, "read_last_n_entries", |contents, _| {
+                    frame::scan(contents).records
+                })?;
+        let records = records.split_off(records.len().saturating_sub(n));
+        Ok(decode_entries(records))
+    }
+
+    async fn recover(&mut self) -> Result<usize, Errors> {
+        let (len, scan) = self
+            .fs
+            .with_contents(self.inode, "recover", |contents, _| {
+                (contents.len(), frame::scan(contents))
+            })?;
+        let truncated = len - scan.valid_len;
+        if truncated > 0 {
+            warn!(
+                "truncating {} corrupt bytes after {} valid records",
+                truncated,
+                scan.records.len()
+            );
+            self.fs.truncate(self.inode, scan.valid_len)?;
+        }
+        self.read_position = self.read_position.min(scan.valid_len);
+        Ok(truncated)
+    }
+
+    fn size(&self) -> usize {
+        self.fs.size(self.inode)
+    }
+
+    async fn close(&mut self) -> Result<(), Errors> {
+        self.fsync().await?;
+        self.closed = true;
+        Ok(())
+    }
+
+    async fn remove(&mut self) -> Result<(), Errors> {
+        self.closed = true;
+        self.fs.unlink(&self.path)
+    }
+}
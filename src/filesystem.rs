@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use tracing::{trace, warn};
+
+use crate::{ErrorClass, ErrorKind, Errors, SimulatedDisk};
+
+/// Identifies a file independently of the paths it is reachable through.
+pub type Inode = u64;
+
+#[derive(Default)]
+struct FileData {
+    contents: Vec<u8>,
+    /// What the last fsync made durable, i.e. what survives a crash for certain.
+    synced_contents: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Directory {
+    entries: BTreeMap<String, Inode>,
+    /// The entries as of the last directory sync.
+    synced_entries: BTreeMap<String, Inode>,
+}
+
+struct FileSystemState {
+    files: HashMap<Inode, FileData>,
+    directories: BTreeMap<PathBuf, Directory>,
+    next_inode: Inode,
+}
+
+/// An in-memory filesystem for simulations: directories of named files whose contents and
+/// directory entries are only durable once synced. Nothing here touches the host disk, so
+/// any number of simulations can run side by side.
+///
+/// Clones share the same filesystem. Relative paths live under the `.` directory, which
+/// always exists.
+#[derive(Clone)]
+pub struct SimulatedFileSystem {
+    disk: SimulatedDisk,
+    state: Arc<Mutex<FileSystemState>>,
+}
+
+impl Default for SimulatedFileSystem {
+    fn default() -> Self {
+        Self::new(SimulatedDisk::default())
+    }
+}
+
+/// Splits `path` into the directory holding it and its name in that directory.
+fn location(path: &Path) -> (PathBuf, String) {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    (directory, name)
+}
+
+fn not_found(kind: ErrorKind, operation: &'static str, path: &Path) -> Errors {
+    Errors::new(kind)
+        .with_operation(operation)
+        .with_class(ErrorClass::Permanent)
+        .with_source(format!("{} does not exist", path.display()))
+}
+
+impl FileSystemState {
+    fn directory(
+        &mut self,
+        path: &Path,
+        kind: ErrorKind,
+        operation: &'static str,
+    ) -> Result<&mut Directory, Errors> {
+        self.directories
+            .get_mut(path)
+            .ok_or_else(|| not_found(kind, operation, path))
+    }
+
+    fn file(
+        &mut self,
+        inode: Inode,
+        kind: ErrorKind,
+        operation: &'static str,
+    ) -> Result<&mut FileData, Errors> {
+        self.files.get_mut(&inode).ok_or_else(|| {
+            Errors::new(kind)
+                .with_operation(operation)
+                .with_class(ErrorClass::Permanent)
+                .with_source("file was deleted")
+        })
+    }
+
+    /// Whether a directory entry points at `inode`, either now or as of its last sync. A
+    /// crash can bring back the synced entries, so their files have to outlive them.
+    fn is_linked(&self, inode: Inode) -> bool {
+        self.directories.values().any(|directory| {
+            directory
+                .entries
+                .values()
+                .chain(directory.synced_entries.values())
+                .any(|&linked| linked == inode)
+        })
+    }
+
+    /// Drops the data of `inode` once no directory entry points at it, returning its size.
+    fn release_if_unlinked(&mut self, inode: Inode) -> usize {
+        if self.is_linked(inode) {
+            return 0;
+        }
+        self.files
+            .remove(&inode)
+            .map_or(0, |file| file.contents.len())
+    }
+}
+
+impl SimulatedFileSystem {
+    pub fn new(disk: SimulatedDisk) -> Self {
+        let directories = BTreeMap::from([(PathBuf::from("."), Directory::default())]);
+        Self {
+            disk,
+            state: Arc::new(Mutex::new(FileSystemState {
+                files: HashMap::new(),
+                directories,
+                next_inode: 0,
+            })),
+        }
+    }
+
+    /// The disk every file's bytes are allocated from.
+    pub fn disk(&self) -> &SimulatedDisk {
+        &self.disk
+    }
+
+    /// Creates `path` and any missing parent directories.
+    pub fn create_dir_all(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        for ancestor in path.ancestors() {
+            if !ancestor.as_os_str().is_empty() {
+                state.directories.entry(ancestor.to_path_buf()).or_default();
+            }
+        }
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        let (directory, name) = location(path);
+        let state = self.state.lock().unwrap();
+        state.directories.contains_key(path)
+            || state
+                .directories
+                .get(&directory)
+                .is_some_and(|directory| directory.entries.contains_key(&name))
+    }
+
+    /// Opens the file at `path`, creating it first if `create` is set. Its directory must
+    /// already exist.
+    pub fn open(&self, path: &Path, create: bool) -> Result<Inode, Errors> {
+        let (directory, name) = location(path);
+        let mut state = self.state.lock().unwrap();
+        let next_inode = state.next_inode;
+        let entries = &mut state
+            .directory(&directory, ErrorKind::FileOpenError, "open_file")?
+            .entries;
+        if let Some(&inode) = entries.get(&name) {
+            return Ok(inode);
+        }
+        if !create {
+            return Err(not_found(ErrorKind::FileOpenError, "open_file", path));
+        }
+        entries.insert(name, next_inode);
+        state.files.insert(next_inode, FileData::default());
+        state.next_inode += 1;
+        trace!("created {} as inode {}", path.display(), next_inode);
+        Ok(next_inode)
+    }
+
+    /// Paths of the files directly inside `directory`, in name order.
+    pub fn list(&self, directory: &Path) -> Result<Vec<PathBuf>, Errors> {
+        let mut state = self.state.lock().unwrap();
+        let listed = state.directory(directory, ErrorKind::FileReadError, "list")?;
+        Ok(listed
+            .entries
+            .keys()
+            .map(|name| directory.join(name))
+            .collect())
+    }
+
+    /// Moves the entry at `from` to `to` in one step, replacing whatever `to` pointed at.
+    pub fn rename(&self, from: &Path, to: &Path) -> Result<(), Errors> {
+        let (from_directory, from_name) = location(from);
+        let (to_directory, to_name) = location(to);
+        let mut state = self.state.lock().unwrap();
+        state.directory(&to_directory, ErrorKind::FileWriteError, "rename")?;
+        let inode = state
+            .directory(&from_directory, ErrorKind::FileWriteError, "rename")?
+            .entries
+            .remove(&from_name)
+            .ok_or_else(|| not_found(ErrorKind::FileWriteError, "rename", from))?;
+        let replaced = state
+            .directory(&to_directory, ErrorKind::FileWriteError, "rename")?
+            .entries
+            .insert(to_name, inode);
+        if let Some(replaced) = replaced.filter(|&replaced| replaced != inode) {
+            let freed = state.release_if_unlinked(replaced);
+            self.disk.free(freed);
+        }
+        Ok(())
+    }
+
+    /// Removes the entry at `path`. The file's space is freed once nothing links to it, which
+    /// for a synced entry is only after its directory is synced again.
+    pub fn unlink(&self, path: &Path) -> Result<(), Errors> {
+        let (directory, name) = location(path);
+        let mut state = self.state.lock().unwrap();
+        let inode = state
+            .directory(&directory, ErrorKind::FileWriteError, "unlink")?
+            .entries
+            .remove(&name)
+            .ok_or_else(|| not_found(ErrorKind::FileWriteError, "unlink", path))?;
+        let freed = state.release_if_unlinked(inode);
+        self.disk.free(freed);
+        Ok(())
+    }
+
+    /// Makes the current entries of `directory` durable, freeing files that were only kept
+    /// around in case a crash brought their old entries back.
+    pub fn sync_dir(&self, directory: &Path) -> Result<(), Errors> {
+        let mut state = self.state.lock().unwrap();
+        let synced = state.directory(directory, ErrorKind::FileSyncError, "sync_dir")?;
+        let previous = std::mem::replace(&mut synced.synced_entries, synced.entries.clone());
+        for inode in previous.into_values() {
+            let freed = state.release_if_unlinked(inode);
+            self.disk.free(freed);
+        }
+        Ok(())
+    }
+
+    /// Loses whatever was written but never fsynced, the way a power loss would. Part of
+    /// each unsynced tail may survive, so the last record of a file can be left torn.
+    ///
+    /// Each directory with changes since its last sync loses all of them with probability
+    /// `metadata_loss`: files created there vanish, and renames and deletes in it are undone.
+    /// Whatever survives is what the disk holds from then on. Returns how many directories
+    /// were rolled back.
+    pub fn crash(&self, rng: &mut ChaCha8Rng, metadata_loss: f64) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let mut rolled_back = 0;
+        for (path, directory) in state.directories.iter_mut() {
+            if directory.entries != directory.synced_entries && rng.gen_bool(metadata_loss) {
+                warn!(
+                    "losing unsynced entries of {}: {:?} -> {:?}",
+                    path.display(),
+                    directory.entries.keys().collect::<Vec<_>>(),
+                    directory.synced_entries.keys().collect::<Vec<_>>()
+                );
+                directory.entries = directory.synced_entries.clone();
+                rolled_back += 1;
+            }
+            directory.synced_entries = directory.entries.clone();
+        }
+        //  Files that lost their last entry are gone
+        let mut inodes: Vec<Inode> = state.files.keys().copied().collect();
+        inodes.sort_unstable();
+        for inode in &inodes {
+            let freed = state.release_if_unlinked(*inode);
+            self.disk.free(freed);
+        }
+
+        let mut inodes: Vec<Inode> = state.files.keys().copied().collect();
+        inodes.sort_unstable();
+        for inode in inodes {
+            let file = state.files.get_mut(&inode).unwrap();
+            let synced_len = file.synced_contents.len();
+            let unsynced_len = file.contents.len().saturating_sub(synced_len);
+            let survived = rng.gen_range(0..=unsynced_len);
+            let mut contents = file.synced_contents.clone();
+            if let Some(tail) = file.contents.get(synced_len..synced_len + survived) {
+                contents.extend_from_slice(tail);
+            }
+            self.disk
+                .free(file.contents.len().saturating_sub(contents.len()));
+            file.contents = contents;
+        }
+        rolled_back
+    }
+
+    pub fn size(&self, inode: Inode) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .files
+            .get(&inode)
+            .map_or(0, |file| file.contents.len())
+    }
+
+    /// Runs `f` over the contents of `inode` and what of them is synced.
+    pub fn with_contents<R>(
+        &self,
+        inode: Inode,
+        operation: &'static str,
+        f: impl FnOnce(&[u8], &[u8]) -> R,
+    ) -> Result<R, Errors> {
+        let mut state = self.state.lock().unwrap();
+        let file = state.file(inode, ErrorKind::FileReadError, operation)?;
+        Ok(f(&file.contents, &file.synced_contents))
+    }
+
+    pub fn append(&self, inode: Inode, data: &[u8]) -> Result<(), Errors> {
+        let mut state = self.state.lock().unwrap();
+        let file = state.file(inode, ErrorKind::FileWriteError, "write")?;
+        self.disk.allocate(data.len())?;
+        file.contents.extend_from_slice(data);
+        Ok(())
+    }
+
+    pub fn fsync(&self, inode: Inode) -> Result<(), Errors> {
+        let mut state = self.state.lock().unwrap();
+        let file = state.file(inode, ErrorKind::FileSyncError, "fsync")?;
+        file.synced_contents = file.contents.clone();
+        Ok(())
+    }
+
+    /// Cuts `inode` down to `len` bytes, freeing the rest.
+    pub fn truncate(&self, inode: Inode, len: usize) -> Result<(), Errors> {
+        let mut state = self.state.lock().unwrap();
+        let file = state.file(inode, ErrorKind::FileWriteError, "truncate")?;
+        self.disk.free(file.contents.len().saturating_sub(len));
+        file.contents.truncate(len);
+        file.synced_contents.truncate(len);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_chacha::rand_core::SeedableRng;
+
+    use super::*;
+
+    fn rng() -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(0)
+    }
+
+    fn contents(fs: &SimulatedFileSystem, path: &Path) -> Vec<u8> {
+        let inode = fs.open(path, false).unwrap();
+        fs.with_contents(inode, "read", |contents, _| contents.to_vec())
+            .unwrap()
+    }
+
+    /// Creates `path` holding `data`, with both synced.
+    fn synced_file(fs: &SimulatedFileSystem, path: &Path, data: &[u8]) -> Inode {
+        let inode = fs.open(path, true).unwrap();
+        fs.append(inode, data).unwrap();
+        fs.fsync(inode).unwrap();
+        fs.sync_dir(Path::new(".")).unwrap();
+        inode
+    }
+
+    #[test]
+    fn crash_keeps_synced_bytes_and_part_of_the_rest() {
+        for seed in 0..20 {
+            let fs = SimulatedFileSystem::default();
+            let inode = synced_file(&fs, Path::new("log"), b"synced");
+            fs.append(inode, b" unsynced").unwrap();
+            fs.crash(&mut ChaCha8Rng::seed_from_u64(seed), 0.0);
+            let survived = contents(&fs, Path::new("log"));
+            assert!(b"synced unsynced".starts_with(&survived), "{:?}", survived);
+            assert!(survived.starts_with(b"synced"), "{:?}", survived);
+            assert_eq!(fs.disk().used(), survived.len());
+        }
+    }
+
+    #[test]
+    fn crash_loses_unsynced_entries_when_metadata_is_lost() {
+        let fs = SimulatedFileSystem::default();
+        synced_file(&fs, Path::new("kept"), b"kept");
+        let created = fs.open(Path::new("created"), true).unwrap();
+        fs.append(created, b"never linked durably").unwrap();
+        fs.fsync(created).unwrap();
+
+        assert_eq!(fs.crash(&mut rng(), 1.0), 1);
+        assert!(!fs.exists(Path::new("created")));
+        assert_eq!(
+            fs.list(Path::new(".")).unwrap(),
+            vec![PathBuf::from("./kept")]
+        );
+        assert_eq!(fs.disk().used(), 4);
+    }
+
+    #[test]
+    fn entries_that_survive_a_crash_are_synced_from_then_on() {
+        let fs = SimulatedFileSystem::default();
+        let created = fs.open(Path::new("created"), true).unwrap();
+        fs.append(created, b"data").unwrap();
+        fs.fsync(created).unwrap();
+        assert_eq!(fs.crash(&mut rng(), 0.0), 0);
+        assert_eq!(fs.crash(&mut rng(), 1.0), 0);
+        assert_eq!(contents(&fs, Path::new("created")), b"data");
+    }
+
+    #[test]
+    fn crash_undoes_an_unsynced_rename_over_an_existing_file() {
+        let fs = SimulatedFileSystem::default();
+        synced_file(&fs, Path::new("config"), b"old");
+        let replacement = fs.open(Path::new("config.tmp"), true).unwrap();
+        fs.append(replacement, b"new").unwrap();
+        fs.fsync(replacement).unwrap();
+        fs.rename(Path::new("config.tmp"), Path::new("config"))
+            .unwrap();
+        assert_eq!(contents(&fs, Path::new("config")), b"new");
+        //  The old file can still come back, so its space isn't freed yet
+        assert_eq!(fs.disk().used(), 6);
+
+        fs.crash(&mut rng(), 1.0);
+        assert_eq!(contents(&fs, Path::new("config")), b"old");
+        assert_eq!(
+            fs.list(Path::new(".")).unwrap(),
+            vec![PathBuf::from("./config")]
+        );
+        assert_eq!(fs.disk().used(), 3);
+    }
+
+    #[test]
+    fn unlinked_files_are_freed_once_the_directory_is_synced() {
+        let fs = SimulatedFileSystem::default();
+        synced_file(&fs, Path::new("old"), b"old data");
+        fs.unlink(Path::new("old")).unwrap();
+        assert!(!fs.exists(Path::new("old")));
+        assert_eq!(fs.disk().used(), 8);
+
+        fs.sync_dir(Path::new(".")).unwrap();
+        assert_eq!(fs.disk().used(), 0);
+        fs.crash(&mut rng(), 1.0);
+        assert!(!fs.exists(Path::new("old")));
+    }
+
+    #[test]
+    fn unlinked_files_come_back_if_the_unlink_was_never_synced() {
+        let fs = SimulatedFileSystem::default();
+        synced_file(&fs, Path::new("old"), b"old data");
+        fs.unlink(Path::new("old")).unwrap();
+        fs.crash(&mut rng(), 1.0);
+        assert_eq!(contents(&fs, Path::new("old")), b"old data");
+    }
+}
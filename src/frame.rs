@@ -0,0 +1,129 @@
+//! On-disk record framing: `[length: u32 LE][crc32 of payload: u32 LE][payload]`.
+//!
+//! A frame whose header is cut short, whose length runs past the end of the file or whose
+//! checksum doesn't match marks the end of the valid log; everything from there on is a
+//! torn or corrupt tail.
+
+pub const HEADER_LEN: usize = 8;
+
+/// Frames larger than this are treated as garbage rather than trusted.
+const MAX_PAYLOAD_LEN: usize = 16 * 1024 * 1024;
+
+/// Bytes taken up on disk by a record of `payload_len` bytes.
+pub fn framed_len(payload_len: usize) -> usize {
+    HEADER_LEN + payload_len
+}
+
+pub fn encode(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&crc32(payload).to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// The result of walking a buffer frame by frame from its start.
+#[derive(Clone, Debug, Default)]
+pub struct Scan {
+    /// Byte offset of each valid frame along with its payload.
+    pub records: Vec<(usize, Vec<u8>)>,
+    /// Length of the prefix made up of valid frames. Anything after it is corrupt.
+    pub valid_len: usize,
+}
+
+pub fn scan(bytes: &[u8]) -> Scan {
+    let mut records = Vec::new();
+    let mut position = 0;
+    while let Some((payload, frame_len)) = decode_at(bytes, position) {
+        records.push((position, payload.to_vec()));
+        position += frame_len;
+    }
+    Scan {
+        records,
+        valid_len: position,
+    }
+}
+
+/// Decodes the frame starting at `position`, returning its payload and total length.
+fn decode_at(bytes: &[u8], position: usize) -> Option<(&[u8], usize)> {
+    let header = bytes.get(position..position + HEADER_LEN)?;
+    let len = u32::from_le_bytes(header[0..4].try_into().ok()?) as usize;
+    let checksum = u32::from_le_bytes(header[4..8].try_into().ok()?);
+    if len > MAX_PAYLOAD_LEN {
+        return None;
+    }
+    let start = position + HEADER_LEN;
+    let payload = bytes.get(start..start + len)?;
+    (crc32(payload) == checksum).then_some((payload, HEADER_LEN + len))
+}
+
+/// CRC-32 (IEEE), computed bitwise. Records are small so a lookup table isn't worth it.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_ieee_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn scan_reads_back_every_encoded_record() {
+        let bytes = [encode(b"first"), encode(b""), encode(b"third")].concat();
+        let scan = scan(&bytes);
+        assert_eq!(scan.valid_len, bytes.len());
+        assert_eq!(
+            scan.records,
+            vec![
+                (0, b"first".to_vec()),
+                (framed_len(5), Vec::new()),
+                (framed_len(5) + framed_len(0), b"third".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_stops_at_a_torn_tail() {
+        let whole = [encode(b"synced"), encode(b"unsynced")].concat();
+        for cut in framed_len(6)..whole.len() {
+            let scan = scan(&whole[..cut]);
+            assert_eq!(
+                scan.records,
+                vec![(0, b"synced".to_vec())],
+                "cut at {}",
+                cut
+            );
+            assert_eq!(scan.valid_len, framed_len(6), "cut at {}", cut);
+        }
+    }
+
+    #[test]
+    fn scan_stops_at_a_corrupt_frame() {
+        let mut bytes = [encode(b"good"), encode(b"flipped"), encode(b"after")].concat();
+        bytes[framed_len(4) + HEADER_LEN] ^= 0x01;
+        let scan = scan(&bytes);
+        assert_eq!(scan.records, vec![(0, b"good".to_vec())]);
+        assert_eq!(scan.valid_len, framed_len(4));
+    }
+
+    #[test]
+    fn scan_rejects_oversized_lengths() {
+        let mut bytes = ((MAX_PAYLOAD_LEN + 1) as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&crc32(b"").to_le_bytes());
+        bytes.resize(bytes.len() + 64, 0);
+        assert_eq!(scan(&bytes).valid_len, 0);
+    }
+}
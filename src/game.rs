@@ -0,0 +1,112 @@
+//! Levels and scoring for the fault injection game the TUI runs.
+
+use crate::{FaultType, FileFaultType};
+
+/// Points for every step survived.
+const STEP_POINTS: i64 = 1;
+/// Points for every injected fault the workload survived.
+const FAULT_POINTS: i64 = 10;
+/// Points taken off for every record that was written but not synced when the run ended.
+const LOST_RECORD_PENALTY: i64 = 50;
+
+/// One stage of the game: which faults are injected and how often, and how long the
+/// workload has to survive them.
+pub struct Level {
+    pub name: &'static str,
+    pub description: &'static str,
+    faults: Vec<(FaultType, f64)>,
+    /// Steps to survive before the workload is shut down and verified. The level is passed
+    /// when that succeeds.
+    pub steps_to_pass: usize,
+}
+
+impl Level {
+    /// How likely `fault` is on this level. Faults the level doesn't list are never injected.
+    pub fn fault_probability(&self, fault: &FaultType) -> f64 {
+        self.faults
+            .iter()
+            .find(|(other, _)| other == fault)
+            .map_or(0.0, |(_, probability)| *probability)
+    }
+}
+
+/// The levels in the order they unlock, each adding faults or making them more likely.
+pub fn levels() -> Vec<Level> {
+    let connections = vec![
+        (FaultType::KafkaConnectionFailure, 0.3),
+        (FaultType::RedisConnectionFailure, 0.3),
+    ];
+    let config = [
+        connections.clone(),
+        vec![(FaultType::RedisReadFailure, 0.3)],
+    ]
+    .concat();
+    let disk = [
+        config.clone(),
+        vec![
+            (
+                FaultType::FileFaultType(FileFaultType::FileWriteFailure),
+                0.3,
+            ),
+            (
+                FaultType::FileFaultType(FileFaultType::FileSizeExceededFailure),
+                0.2,
+            ),
+        ],
+    ]
+    .concat();
+    let messages = [disk.clone(), vec![(FaultType::KafkaReadFailure, 0.05)]].concat();
+    let everything = messages
+        .iter()
+        .map(|(fault, probability)| (fault.clone(), probability * 1.5))
+        .collect();
+    vec![
+        Level {
+            name: "Flaky connections",
+            description: "Kafka and Redis refuse connections",
+            faults: connections,
+            steps_to_pass: 100,
+        },
+        Level {
+            name: "Unreliable config",
+            description: "Redis reads fail too",
+            faults: config,
+            steps_to_pass: 200,
+        },
+        Level {
+            name: "Failing disk",
+            description: "Writes fail and the disk fills up",
+            faults: disk,
+            steps_to_pass: 300,
+        },
+        Level {
+            name: "Malformed messages",
+            description: "Kafka starts handing out messages that can't be processed",
+            faults: messages,
+            steps_to_pass: 400,
+        },
+        Level {
+            name: "Everything at once",
+            description: "Every fault is 50% more likely",
+            faults: everything,
+            steps_to_pass: 500,
+        },
+    ]
+}
+
+/// How well a run went.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Score {
+    pub steps: usize,
+    /// Injected faults the workload carried on through.
+    pub faults_absorbed: usize,
+    /// Records written but not synced when the run ended, which a crash would lose.
+    pub records_lost: usize,
+}
+
+impl Score {
+    pub fn total(&self) -> i64 {
+        self.steps as i64 * STEP_POINTS + self.faults_absorbed as i64 * FAULT_POINTS
+            - self.records_lost as i64 * LOST_RECORD_PENALTY
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::Path;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Errors, FaultType, FileSnapshot, Metrics, RotationPolicy, SimulatedDisk, SimulatedFileSystem,
+};
+
+/// A message consumed from Kafka along with where it came from.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KafkaMessage {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub payload: String,
+}
+
+#[async_trait]
+pub trait IO: Send {
+    async fn create_kafka_consumer(
+        &mut self,
+        group_id: &str,
+        broker: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Result<(), Errors>;
+    async fn connect_to_redis(&mut self, url: &str) -> Result<(), Errors>;
+    /// Opens the segmented output log at `path`, recovering any segments already there.
+    async fn open_file(&mut self, path: &Path, rotation: RotationPolicy) -> Result<(), Errors>;
+    async fn read_kafka_message(&mut self) -> Result<Option<KafkaMessage>, Errors>;
+    /// Marks every message up to and including `offset` as processed, so a restarted
+    /// consumer resumes after it.
+    async fn commit_kafka_offset(
+        &mut self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> Result<(), Errors>;
+    async fn get_redis_config(&mut self, key: &str) -> Result<String, Errors>;
+    async fn read_file(&mut self, size: usize) -> Result<Vec<u8>, Errors>;
+    async fn read_last_n_entries(&mut self, n: usize) -> Result<Vec<String>, Errors>;
+    async fn write_to_file(&mut self, data: &str) -> Result<usize, Errors>;
+    /// Appends one record per entry of `data` in a single write.
+    async fn write_batch_to_file(&mut self, data: &[String]) -> Result<usize, Errors>;
+    /// Makes everything written to the file so far durable.
+    async fn sync_file(&mut self) -> Result<(), Errors>;
+    /// Atomically moves `from` to `to`, replacing `to` if it exists. The new name is only
+    /// durable once its directory is synced.
+    async fn rename_file(&mut self, from: &Path, to: &Path) -> Result<(), Errors>;
+    /// Makes the entries of `directory` durable: files created in it, renamed into or out of
+    /// it and deleted from it.
+    async fn sync_dir(&mut self, directory: &Path) -> Result<(), Errors>;
+    /// Appends `records` to the standalone file at `path`, creating it if needed, and syncs it.
+    async fn write_records(&mut self, path: &Path, records: &[String]) -> Result<(), Errors>;
+    /// Every intact record of the standalone file at `path`, or none if it doesn't exist.
+    async fn read_records(&mut self, path: &Path) -> Result<Vec<String>, Errors>;
+    /// Deletes the file at `path` if it exists. Like a rename, this is only durable once its
+    /// directory is synced.
+    async fn remove_file(&mut self, path: &Path) -> Result<(), Errors>;
+    fn generate_jitter(&mut self, base_delay: Duration) -> Duration;
+    async fn sleep(&mut self, duration: Duration);
+    /// Current time according to the IO's clock, virtual when simulated.
+    fn now(&self) -> Duration;
+    /// Whether the process has been asked to stop, e.g. by SIGTERM.
+    fn shutdown_requested(&self) -> bool {
+        false
+    }
+    /// The registry operations are recorded in, when this IO is metered.
+    fn metrics(&self) -> Option<&Metrics> {
+        None
+    }
+    /// Returns the simulation-only controls when this IO is simulated.
+    fn simulation(&mut self) -> Option<&mut dyn SimulationControl> {
+        None
+    }
+}
+
+/// Capabilities that only exist for simulated IO: fault introspection, control over
+/// virtual time, crash triggers and state inspection. Real IO never implements this.
+pub trait SimulationControl {
+    fn get_generated_faults(&mut self) -> Vec<FaultType>;
+    /// Like `get_generated_faults`, with the virtual time each fault was injected at.
+    fn take_timed_faults(&mut self) -> Vec<(FaultType, Duration)>;
+    fn advance_clock(&mut self, duration: Duration);
+    fn fault_probability(&self, fault: &FaultType) -> f64;
+    /// Changes how likely `fault` is to be injected from now on, clamped to `0.0..=1.0`.
+    fn set_fault_probability(&mut self, fault: FaultType, probability: f64);
+    fn crash(&mut self);
+    /// Asks for a shutdown once the virtual clock reaches `at`, like a SIGTERM arriving then.
+    fn schedule_shutdown(&mut self, at: Duration);
+    /// Snapshot of the segment currently being written.
+    fn file_snapshot(&self) -> Option<FileSnapshot>;
+    /// Snapshots of every segment of the output log, oldest first.
+    fn segment_snapshots(&self) -> Vec<FileSnapshot>;
+    /// The disk simulated files are allocated from. Resize it to simulate running out of space.
+    fn disk(&self) -> &SimulatedDisk;
+    /// The in-memory filesystem simulated files live in.
+    fn file_system(&self) -> &SimulatedFileSystem;
+    /// The last Kafka offset committed, which a restarted consumer resumes after.
+    fn committed_offset(&self) -> Option<i64>;
+}
+
+/// Drains the faults injected so far, or nothing when running against real IO.
+pub fn take_generated_faults(io: &mut dyn IO) -> Vec<FaultType> {
+    io.simulation()
+        .map(|sim| sim.get_generated_faults())
+        .unwrap_or_default()
+}
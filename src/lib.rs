@@ -0,0 +1,70 @@
+use tracing::{info, trace};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::writer::MakeWriterExt;
+
+mod circuit_breaker;
+mod clock;
+mod config_cache;
+mod disk;
+mod errors;
+mod fault;
+mod file;
+mod filesystem;
+pub mod frame;
+mod game;
+mod io;
+mod metrics;
+mod pipeline;
+mod queue;
+mod real;
+pub mod report;
+mod retry;
+mod segment;
+mod simulated;
+pub mod simulator;
+mod sink;
+pub mod tui;
+mod workload;
+
+pub use circuit_breaker::{CircuitBreaker, CircuitState};
+pub use clock::{Clock, RealClock, SimulatedClock};
+pub use config_cache::{CachedConfig, ConfigCache};
+pub use disk::SimulatedDisk;
+pub use errors::{ErrorClass, ErrorKind, Errors};
+pub use fault::{FaultType, FileFaultType};
+pub use file::{File, FileSnapshot, RealFile, SimulatedFile};
+pub use filesystem::SimulatedFileSystem;
+pub use io::{take_generated_faults, KafkaMessage, SimulationControl, IO};
+pub use metrics::{MeteredIO, Metrics};
+pub use pipeline::PipelineWorkload;
+pub use queue::WriteQueue;
+pub use real::RealIO;
+pub use retry::{Backoff, Retry, RetryPolicy};
+pub use segment::{RotationPolicy, SegmentedLog};
+pub use simulated::SimulatedIO;
+pub use sink::{BatchPolicy, IdempotentSink, OutputRecord};
+pub use workload::Workload;
+
+pub enum LogOptions {
+    Console,
+    File,
+}
+
+pub fn init_tracing(option: LogOptions) {
+    match option {
+        LogOptions::Console => {
+            tracing_subscriber::fmt::init();
+            info!("Initialising tracing to write to stdout");
+        }
+        LogOptions::File => {
+            let file_appender = RollingFileAppender::new(Rotation::DAILY, ".", "debug.log");
+            let subscriber = tracing_subscriber::fmt()
+                .with_writer(file_appender.with_max_level(tracing::Level::TRACE))
+                .with_max_level(tracing::Level::TRACE)
+                .finish();
+            tracing::subscriber::set_global_default(subscriber)
+                .expect("setting default subscriber failed");
+            trace!("Initialising tracing to write to a file");
+        }
+    }
+}
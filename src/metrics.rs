@@ -0,0 +1,479 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+use crate::{Errors, FaultType, KafkaMessage, RotationPolicy, SimulationControl, IO};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0];
+
+/// How long the metrics server waits for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Observations per bucket of `LATENCY_BUCKETS`, not cumulative.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&bound| value <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct OperationStats {
+    count: u64,
+    errors: u64,
+    latency: Histogram,
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    operations: BTreeMap<&'static str, OperationStats>,
+    retries: BTreeMap<String, u64>,
+    faults: BTreeMap<String, u64>,
+    counters: BTreeMap<&'static str, u64>,
+    gauges: BTreeMap<&'static str, f64>,
+}
+
+/// Counters, gauges and latency histograms for everything a workload does through its IO.
+/// Clones share the same registry, so it can be rendered from another task.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one call of `operation`, how long it took and whether it failed.
+    pub fn observe<T>(
+        &self,
+        operation: &'static str,
+        latency: Duration,
+        result: &Result<T, Errors>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let stats = state.operations.entry(operation).or_default();
+        stats.count += 1;
+        if result.is_err() {
+            stats.errors += 1;
+        }
+        stats.latency.observe(latency.as_secs_f64());
+    }
+
+    /// Counts a retry of the operation that failed with `err`.
+    pub fn record_retry(&self, err: &Errors) {
+        let operation = err.operation().unwrap_or("unknown").to_string();
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .retries
+            .entry(operation)
+            .or_default() += 1;
+    }
+
+    pub fn record_faults(&self, faults: &[FaultType]) {
+        let mut state = self.state.lock().unwrap();
+        for fault in faults {
+            *state.faults.entry(fault_label(fault)).or_default() += 1;
+        }
+    }
+
+    /// Adds `by` to the workload-defined counter `name`.
+    pub fn increment(&self, name: &'static str, by: u64) {
+        *self.state.lock().unwrap().counters.entry(name).or_default() += by;
+    }
+
+    pub fn counter(&self, name: &str) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.counters.get(name).copied().unwrap_or(0)
+    }
+
+    /// Calls and failed calls so far, by operation.
+    pub fn operations(&self) -> BTreeMap<&'static str, (u64, u64)> {
+        let state = self.state.lock().unwrap();
+        state
+            .operations
+            .iter()
+            .map(|(&operation, stats)| (operation, (stats.count, stats.errors)))
+            .collect()
+    }
+
+    /// Retries so far, by the operation that was retried.
+    pub fn retries(&self) -> BTreeMap<String, u64> {
+        self.state.lock().unwrap().retries.clone()
+    }
+
+    pub fn set_gauge(&self, name: &'static str, value: f64) {
+        self.state.lock().unwrap().gauges.insert(name, value);
+    }
+
+    /// Everything recorded so far in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP dst_io_operations_total IO operations performed.\n");
+        out.push_str("# TYPE dst_io_operations_total counter\n");
+        for (operation, stats) in &state.operations {
+            let _ = writeln!(
+                out,
+                "dst_io_operations_total{{operation=\"{}\"}} {}",
+                operation, stats.count
+            );
+        }
+        out.push_str("# HELP dst_io_errors_total IO operations that returned an error.\n");
+        out.push_str("# TYPE dst_io_errors_total counter\n");
+        for (operation, stats) in &state.operations {
+            let _ = writeln!(
+                out,
+                "dst_io_errors_total{{operation=\"{}\"}} {}",
+                operation, stats.errors
+            );
+        }
+        out.push_str(
+            "# HELP dst_io_latency_seconds Latency of IO operations, virtual when simulated.\n",
+        );
+        out.push_str("# TYPE dst_io_latency_seconds histogram\n");
+        for (operation, stats) in &state.operations {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&stats.latency.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "dst_io_latency_seconds_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                    operation, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "dst_io_latency_seconds_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+                operation, stats.latency.count
+            );
+            let _ = writeln!(
+                out,
+                "dst_io_latency_seconds_sum{{operation=\"{}\"}} {}",
+                operation, stats.latency.sum
+            );
+            let _ = writeln!(
+                out,
+                "dst_io_latency_seconds_count{{operation=\"{}\"}} {}",
+                operation, stats.latency.count
+            );
+        }
+        out.push_str("# HELP dst_retries_total Failed attempts that were retried.\n");
+        out.push_str("# TYPE dst_retries_total counter\n");
+        for (operation, count) in &state.retries {
+            let _ = writeln!(
+                out,
+                "dst_retries_total{{operation=\"{}\"}} {}",
+                operation, count
+            );
+        }
+        out.push_str("# HELP dst_faults_injected_total Faults injected by the simulator.\n");
+        out.push_str("# TYPE dst_faults_injected_total counter\n");
+        for (fault, count) in &state.faults {
+            let _ = writeln!(
+                out,
+                "dst_faults_injected_total{{fault=\"{}\"}} {}",
+                fault, count
+            );
+        }
+        for (name, value) in &state.counters {
+            let _ = writeln!(out, "# TYPE dst_{}_total counter", name);
+            let _ = writeln!(out, "dst_{}_total {}", name, value);
+        }
+        for (name, value) in &state.gauges {
+            let _ = writeln!(out, "# TYPE dst_{} gauge", name);
+            let _ = writeln!(out, "dst_{} {}", name, value);
+        }
+        out
+    }
+
+    /// Serves `render_prometheus` over HTTP at `addr` from a background task, returning the
+    /// bound address once it is listening. Must be called inside a tokio runtime.
+    pub async fn serve(self, addr: &str) -> Result<SocketAddr, std::io::Error> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        info!("serving metrics on http://{}/metrics", local_addr);
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("failed to accept a metrics connection: {}", e);
+                        continue;
+                    }
+                };
+                //  A client that never sends its request must not hold up the next scrape
+                tokio::spawn(self.clone().respond(stream, peer));
+            }
+        });
+        Ok(local_addr)
+    }
+
+    async fn respond(self, mut stream: TcpStream, peer: SocketAddr) {
+        //  Every request gets the metrics, whatever its path
+        let mut request = [0; 1024];
+        if timeout(REQUEST_TIMEOUT, stream.read(&mut request))
+            .await
+            .is_err()
+        {
+            warn!("no metrics request from {} in {:?}", peer, REQUEST_TIMEOUT);
+            return;
+        }
+        let body = self.render_prometheus();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        if let Err(e) = stream.write_all(response.as_bytes()).await {
+            warn!("failed to send metrics to {}: {}", peer, e);
+        }
+    }
+}
+
+fn fault_label(fault: &FaultType) -> String {
+    match fault {
+        FaultType::FileFaultType(file_fault) => format!("{:?}", file_fault),
+        _ => format!("{:?}", fault),
+    }
+}
+
+/// Wraps any `IO` and records the count, errors and latency of every operation on the
+/// wrapped IO's own clock, so simulated runs are timed in virtual time.
+pub struct MeteredIO<I> {
+    inner: I,
+    metrics: Metrics,
+}
+
+impl<I: IO> MeteredIO<I> {
+    /// Records into `metrics`, which the caller can keep a clone of to render it.
+    pub fn new(inner: I, metrics: Metrics) -> Self {
+        Self { inner, metrics }
+    }
+
+    pub fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+
+    fn observe<T>(&self, operation: &'static str, started: Duration, result: &Result<T, Errors>) {
+        let latency = self.inner.now().saturating_sub(started);
+        self.metrics.observe(operation, latency, result);
+    }
+}
+
+#[async_trait]
+impl<I: IO> IO for MeteredIO<I> {
+    async fn create_kafka_consumer(
+        &mut self,
+        group_id: &str,
+        broker: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self
+            .inner
+            .create_kafka_consumer(group_id, broker, topic, partition)
+            .await;
+        self.observe("create_kafka_consumer", started, &result);
+        result
+    }
+
+    async fn connect_to_redis(&mut self, url: &str) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self.inner.connect_to_redis(url).await;
+        self.observe("connect_to_redis", started, &result);
+        result
+    }
+
+    async fn open_file(&mut self, path: &Path, rotation: RotationPolicy) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self.inner.open_file(path, rotation).await;
+        self.observe("open_file", started, &result);
+        result
+    }
+
+    async fn read_kafka_message(&mut self) -> Result<Option<KafkaMessage>, Errors> {
+        let started = self.inner.now();
+        let result = self.inner.read_kafka_message().await;
+        self.observe("read_kafka_message", started, &result);
+        result
+    }
+
+    async fn commit_kafka_offset(
+        &mut self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self
+            .inner
+            .commit_kafka_offset(topic, partition, offset)
+            .await;
+        self.observe("commit_kafka_offset", started, &result);
+        result
+    }
+
+    async fn get_redis_config(&mut self, key: &str) -> Result<String, Errors> {
+        let started = self.inner.now();
+        let result = self.inner.get_redis_config(key).await;
+        self.observe("get_redis_config", started, &result);
+        result
+    }
+
+    async fn read_file(&mut self, size: usize) -> Result<Vec<u8>, Errors> {
+        let started = self.inner.now();
+        let result = self.inner.read_file(size).await;
+        self.observe("read_file", started, &result);
+        result
+    }
+
+    async fn read_last_n_entries(&mut self, n: usize) -> Result<Vec<String>, Errors> {
+        let started = self.inner.now();
+        let result = self.inner.read_last_n_entries(n).await;
+        self.observe("read_last_n_entries", started, &result);
+        result
+    }
+
+    async fn write_to_file(&mut self, data: &str) -> Result<usize, Errors> {
+        let started = self.inner.now();
+        let result = self.inner.write_to_file(data).await;
+        self.observe("write_to_file", started, &result);
+        result
+    }
+
+    async fn write_batch_to_file(&mut self, data: &[String]) -> Result<usize, Errors> {
+        let started = self.inner.now();
+        let result = self.inner.write_batch_to_file(data).await;
+        self.observe("write_batch_to_file", started, &result);
+        result
+    }
+
+    async fn sync_file(&mut self) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self.inner.sync_file().await;
+        self.observe("sync_file", started, &result);
+        result
+    }
+
+    async fn rename_file(&mut self, from: &Path, to: &Path) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self.inner.rename_file(from, to).await;
+        self.observe("rename_file", started, &result);
+        result
+    }
+
+    async fn sync_dir(&mut self, directory: &Path) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self.inner.sync_dir(directory).await;
+        self.observe("sync_dir", started, &result);
+        result
+    }
+
+    async fn write_records(&mut self, path: &Path, records: &[String]) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self.inner.write_records(path, records).await;
+        self.observe("write_records", started, &result);
+        result
+    }
+
+    async fn read_records(&mut self, path: &Path) -> Result<Vec<String>, Errors> {
+        let started = self.inner.now();
+        let result = self.inner.read_records(path).await;
+        self.observe("read_records", started, &result);
+        result
+    }
+
+    async fn remove_file(&mut self, path: &Path) -> Result<(), Errors> {
+        let started = self.inner.now();
+        let result = self.inner.remove_file(path).await;
+        self.observe("remove_file", started, &result);
+        result
+    }
+
+    fn generate_jitter(&mut self, base_delay: Duration) -> Duration {
+        self.inner.generate_jitter(base_delay)
+    }
+
+    async fn sleep(&mut self, duration: Duration) {
+        self.inner.sleep(duration).await
+    }
+
+    fn now(&self) -> Duration {
+        self.inner.now()
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        self.inner.shutdown_requested()
+    }
+
+    fn metrics(&self) -> Option<&Metrics> {
+        Some(&self.metrics)
+    }
+
+    fn simulation(&mut self) -> Option<&mut dyn SimulationControl> {
+        self.inner.simulation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn scrape(addr: SocketAddr) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn idle_client_does_not_block_scrapes() {
+        let metrics = Metrics::new();
+        metrics.set_gauge("write_queue_depth", 3.0);
+        let addr = metrics.serve("127.0.0.1:0").await.unwrap();
+
+        let _idle = TcpStream::connect(addr).await.unwrap();
+        let response = timeout(Duration::from_secs(1), scrape(addr))
+            .await
+            .expect("scrape blocked behind an idle connection");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("dst_write_queue_depth 3"));
+    }
+}
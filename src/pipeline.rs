@@ -0,0 +1,654 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tracing::{error, info, trace, warn};
+
+use crate::segment::segment_directory;
+use crate::{
+    BatchPolicy, CachedConfig, CircuitBreaker, ConfigCache, ErrorKind, Errors, IdempotentSink,
+    KafkaMessage, OutputRecord, RetryPolicy, RotationPolicy, Workload, WriteQueue, IO,
+};
+
+/// How many times shutdown tries to flush what is left before giving up on it.
+const SHUTDOWN_FLUSH_ATTEMPTS: usize = 5;
+
+/// Payloads this short can't be a real message.
+const MIN_PAYLOAD_LEN: usize = 11;
+
+/// Adds to a workload counter when the IO is metered.
+fn count(io: &mut dyn IO, name: &'static str, by: usize) {
+    if let Some(metrics) = io.metrics() {
+        metrics.increment(name, by as u64);
+    }
+}
+
+/// The reference workload: read a message from Kafka, look up config in Redis and
+/// append the combined record to a file.
+pub struct PipelineWorkload {
+    config_key: String,
+    counter: usize,
+    written_messages: Vec<String>,
+    queue: WriteQueue,
+    /// Written by the last flush but not yet synced and acknowledged.
+    unsynced: Vec<OutputRecord>,
+    batching: BatchPolicy,
+    batch_started: Option<Duration>,
+    pending_message: Option<KafkaMessage>,
+    /// Where messages that can never be processed are set aside.
+    dead_letter_path: PathBuf,
+    sink: IdempotentSink,
+    rotation: RotationPolicy,
+    connect_retry: RetryPolicy,
+    read_retry: RetryPolicy,
+    kafka_breaker: CircuitBreaker,
+    redis_breaker: CircuitBreaker,
+    idle_backoff: Duration,
+    config_cache: ConfigCache,
+    serving_stale_config: bool,
+    disk_full: bool,
+    backpressure: bool,
+    config_status: String,
+    status: Vec<String>,
+}
+
+impl PipelineWorkload {
+    pub fn new(config_key: &str) -> Self {
+        Self {
+            config_key: config_key.to_string(),
+            counter: 0,
+            written_messages: Vec::new(),
+            queue: WriteQueue::default(),
+            unsynced: Vec::new(),
+            batching: BatchPolicy::default(),
+            batch_started: None,
+            pending_message: None,
+            dead_letter_path: PathBuf::from("dead-letter.txt"),
+            sink: IdempotentSink::default(),
+            rotation: RotationPolicy::default(),
+            connect_retry: RetryPolicy::default(),
+            read_retry: RetryPolicy::default(),
+            kafka_breaker: CircuitBreaker::new("kafka"),
+            redis_breaker: CircuitBreaker::new("redis"),
+            idle_backoff: Duration::from_millis(100),
+            config_cache: ConfigCache::default(),
+            serving_stale_config: false,
+            disk_full: false,
+            backpressure: false,
+            config_status: String::new(),
+            status: Vec::new(),
+        }
+    }
+
+    /// Retry policy for connecting to Kafka and Redis during `init`.
+    pub fn with_connect_retry(mut self, policy: RetryPolicy) -> Self {
+        self.connect_retry = policy;
+        self
+    }
+
+    /// Retry policy for reading a message from Kafka and config from Redis on every step.
+    pub fn with_read_retry(mut self, policy: RetryPolicy) -> Self {
+        self.read_retry = policy;
+        self
+    }
+
+    /// How many records are written and synced together.
+    pub fn with_batching(mut self, batching: BatchPolicy) -> Self {
+        self.batching = batching;
+        self
+    }
+
+    /// When the output log moves on to a new segment file.
+    pub fn with_rotation(mut self, rotation: RotationPolicy) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// How many records may wait to be written before they spill or consumption pauses.
+    pub fn with_write_queue(mut self, queue: WriteQueue) -> Self {
+        self.queue = queue;
+        self
+    }
+
+    /// Where malformed messages are written instead of stopping the pipeline.
+    pub fn with_dead_letter_file(mut self, path: &Path) -> Self {
+        self.dead_letter_path = path.to_path_buf();
+        self
+    }
+
+    pub fn with_config_cache(mut self, config_cache: ConfigCache) -> Self {
+        self.config_cache = config_cache;
+        self
+    }
+
+    /// Whether the last step ran on config that Redis couldn't confirm.
+    pub fn serving_stale_config(&self) -> bool {
+        self.serving_stale_config
+    }
+
+    /// Whether consumption is paused because the last write found the disk full.
+    pub fn disk_full(&self) -> bool {
+        self.disk_full
+    }
+
+    /// Records waiting to be written, oldest first.
+    pub fn queued_writes(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Of the queued records, how many are waiting in the spill file.
+    pub fn spilled_writes(&self) -> usize {
+        self.queue.pending_spilled()
+    }
+
+    /// Whether consumption is paused because the write queue is full.
+    pub fn backpressure(&self) -> bool {
+        self.backpressure
+    }
+
+    /// Records written but not yet synced and acknowledged.
+    pub fn unacknowledged_writes(&self) -> usize {
+        self.unsynced.len()
+    }
+
+    pub fn with_kafka_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.kafka_breaker = breaker;
+        self
+    }
+
+    pub fn with_redis_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.redis_breaker = breaker;
+        self
+    }
+
+    async fn read_kafka_message(&mut self, io: &mut dyn IO) -> Result<KafkaMessage, Errors> {
+        let mut retry = self.read_retry.start(io);
+        loop {
+            self.kafka_breaker.allow(io.now())?;
+            let result = io.read_kafka_message().await;
+            self.kafka_breaker.record(io.now(), &result);
+            match result {
+                Ok(Some(message)) => return Ok(message),
+                Ok(None) => {
+                    return Err(
+                        Errors::new(ErrorKind::NoKafkaMessage).with_operation("read_kafka_message")
+                    );
+                }
+                Err(err) => retry.backoff(io, err).await?,
+            }
+        }
+    }
+
+    /// Sets a message that can never be processed aside in the dead-letter file, so the
+    /// pipeline moves on past its offset. Should that write fail the message is parked and
+    /// tried again next step.
+    async fn dead_letter(
+        &mut self,
+        io: &mut dyn IO,
+        message: KafkaMessage,
+        reason: Errors,
+    ) -> Result<(), Errors> {
+        let encoded = serde_json::to_string(&message).expect("Kafka messages always serialize");
+        let written = match io.write_records(&self.dead_letter_path, &[encoded]).await {
+            Ok(()) => io.sync_dir(segment_directory(&self.dead_letter_path)).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = written {
+            self.pending_message = Some(message);
+            return self.idle(io, err).await;
+        }
+        warn!("dead-lettered offset {}: {}", message.offset, reason);
+        count(io, "messages_dead_lettered", 1);
+        self.status = vec![format!(
+            "Dead-lettered offset {}: {}",
+            message.offset, reason
+        )];
+        Ok(())
+    }
+
+    /// Refreshes the cached config once it is due. This runs as a timer on `IO::now` ahead
+    /// of each step rather than when a record needs config, so a record never waits on
+    /// Redis while there is a cached value to serve. A failed refresh keeps the old value
+    /// and tries again later.
+    async fn refresh_config(&mut self, io: &mut dyn IO) {
+        if !self.config_cache.refresh_due(&self.config_key, io.now()) {
+            return;
+        }
+        match self.fetch_redis_config(io, &RetryPolicy::never()).await {
+            Ok(value) => {
+                trace!("refreshed config");
+                self.config_cache.insert(&self.config_key, value, io.now());
+            }
+            Err(err) => {
+                warn!("failed to refresh config: {}", err);
+                self.config_cache.refresh_failed(&self.config_key, io.now());
+            }
+        }
+    }
+
+    /// Serves config from the cache, stale or not, as `refresh_config` keeps it up to date.
+    /// Only config that is missing or too old to serve is fetched from Redis here.
+    async fn read_redis_config(&mut self, io: &mut dyn IO) -> Result<String, Errors> {
+        match self.config_cache.get(&self.config_key, io.now()) {
+            CachedConfig::Fresh(value) => {
+                self.serving_stale_config = false;
+                self.config_status = "Read config from cache".to_string();
+                Ok(value)
+            }
+            CachedConfig::Stale { value, age } => {
+                warn!("serving config that is {:?} old", age);
+                self.serving_stale_config = true;
+                self.config_status = format!("Serving stale config ({:?} old)", age);
+                Ok(value)
+            }
+            CachedConfig::Missing => {
+                self.serving_stale_config = false;
+                let policy = self.read_retry.clone();
+                let value = self.fetch_redis_config(io, &policy).await?;
+                self.config_cache
+                    .insert(&self.config_key, value.clone(), io.now());
+                self.config_status = "Read messages from Redis".to_string();
+                Ok(value)
+            }
+        }
+    }
+
+    async fn fetch_redis_config(
+        &mut self,
+        io: &mut dyn IO,
+        policy: &RetryPolicy,
+    ) -> Result<String, Errors> {
+        let mut retry = policy.start(io);
+        loop {
+            self.redis_breaker.allow(io.now())?;
+            let result = io.get_redis_config(&self.config_key).await;
+            self.redis_breaker.record(io.now(), &result);
+            match result {
+                Ok(config) => return Ok(config),
+                Err(err) => retry.backoff(io, err).await?,
+            }
+        }
+    }
+
+    /// Whether the queued records should go out now: the batch is full, its oldest record
+    /// has waited long enough, or an earlier flush still has to be synced.
+    fn batch_due(&self, now: Duration) -> bool {
+        !self.unsynced.is_empty()
+            || self.queue.len() >= self.batching.max_records()
+            || self
+                .batch_started
+                .is_some_and(|started| now.saturating_sub(started) >= self.batching.max_delay())
+    }
+
+    /// Group commit: writes every queued record in one call, fsyncs, and only then commits
+    /// the Kafka offset of the last one. Anything that fails stays queued, or written but
+    /// unacknowledged, for the next flush. Running out of space pauses consumption until a
+    /// later flush gets through. Spilled records go out first, since they are the oldest.
+    async fn flush(&mut self, io: &mut dyn IO) {
+        let records = match self.queue.pending(io).await {
+            Ok(records) => records,
+            Err(e) => {
+                error!("failed to read spilled records: {}", e);
+                return;
+            }
+        };
+        if !records.is_empty() {
+            match self.sink.write_batch(io, &records).await {
+                Ok(written) => {
+                    count(io, "messages_written", written.len());
+                    self.written_messages.extend(written);
+                    self.queue.written();
+                    self.unsynced.extend(records);
+                    self.batch_started = None;
+                    if self.disk_full {
+                        warn!("disk space available again, resuming consumption");
+                    }
+                    self.disk_full = false;
+                }
+                Err(e) if e == ErrorKind::DiskFull => {
+                    count(io, "messages_failed", records.len());
+                    if !self.disk_full {
+                        error!(
+                            "ALERT: disk full, pausing consumption with {} records queued: {}",
+                            self.queue.len(),
+                            e
+                        );
+                    }
+                    self.disk_full = true;
+                    return;
+                }
+                Err(e) => {
+                    count(io, "messages_failed", records.len());
+                    error!("failed to write to file: {}", e);
+                    return;
+                }
+            }
+        }
+
+        let Some(last) = self.unsynced.last() else {
+            return;
+        };
+        if let Err(e) = io.sync_file().await {
+            error!("failed to sync {} records: {}", self.unsynced.len(), e);
+            return;
+        }
+        match io
+            .commit_kafka_offset(&last.topic, last.partition, last.offset)
+            .await
+        {
+            Ok(()) => {
+                trace!(
+                    "acknowledged {} records up to offset {}",
+                    self.unsynced.len(),
+                    last.offset
+                );
+                self.unsynced.clear();
+            }
+            Err(e) => {
+                error!("failed to commit offset {}: {}", last.offset, e);
+                return;
+            }
+        }
+        //  Should the spill file outlive a crash now, its records are skipped as duplicates
+        if let Err(e) = self.queue.release_spill(io).await {
+            warn!("failed to delete the spill file: {}", e);
+        }
+    }
+
+    /// Makes room in a full queue by moving it to the spill file. Spilled records are as
+    /// durable as written ones, so their offsets are committed too, but only once everything
+    /// older is acknowledged. Returns whether there is room now.
+    async fn spill(&mut self, io: &mut dyn IO) -> bool {
+        if !self.queue.can_spill() || !self.unsynced.is_empty() {
+            return false;
+        }
+        let newest = match self.queue.spill(io).await {
+            Ok(Some(newest)) => newest,
+            Ok(None) => return !self.queue.is_full(),
+            Err(e) => {
+                error!("failed to spill {} records: {}", self.queue.len(), e);
+                return false;
+            }
+        };
+        if let Err(e) = io
+            .commit_kafka_offset(&newest.topic, newest.partition, newest.offset)
+            .await
+        {
+            //  A restart redelivers them and the sink skips what the spill file already has
+            warn!("failed to commit spilled offset {}: {}", newest.offset, e);
+        }
+        true
+    }
+
+    /// A dependency is unavailable for now: skip this step and give it time to come back.
+    async fn idle(&mut self, io: &mut dyn IO, err: Errors) -> Result<(), Errors> {
+        warn!("skipping step {}: {}", self.counter, err);
+        self.status = vec![format!("Waiting for dependencies: {}", err)];
+        if self.batch_due(io.now()) {
+            self.flush(io).await;
+        }
+        io.sleep(self.idle_backoff).await;
+        Ok(())
+    }
+
+    /// One iteration: consume a message, look up its config and queue the record.
+    async fn consume(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        self.counter += 1;
+        trace!("Iteration {}", self.counter);
+
+        //  With the disk full new records would only pile up in memory, so stop consuming
+        //  until the backlog gets written
+        if self.disk_full {
+            self.flush(io).await;
+            if self.disk_full {
+                self.status = vec![format!(
+                    "ALERT: Disk full, consumption paused with {} records queued",
+                    self.queue.len()
+                )];
+                io.sleep(self.idle_backoff).await;
+                return Ok(());
+            }
+        }
+
+        //  Backpressure: a full queue spills if it can, otherwise nothing more is consumed
+        //  until a flush drains it
+        if self.queue.is_full() {
+            self.flush(io).await;
+            if self.queue.is_full() && !self.spill(io).await {
+                if !self.backpressure {
+                    warn!(
+                        "write queue full with {} records, pausing consumption",
+                        self.queue.len()
+                    );
+                }
+                self.backpressure = true;
+                self.status = vec![format!(
+                    "Write queue full, consumption paused with {} records queued",
+                    self.queue.len()
+                )];
+                io.sleep(self.idle_backoff).await;
+                return Ok(());
+            }
+        }
+        if self.backpressure {
+            warn!("write queue has room again, resuming consumption");
+        }
+        self.backpressure = false;
+
+        //  Get Kafka message, unless one is still parked from a step that couldn't finish
+        let kafka_message = match self.pending_message.take() {
+            Some(message) => message,
+            None => match self.read_kafka_message(io).await {
+                Ok(message) => {
+                    count(io, "messages_consumed", 1);
+                    message
+                }
+                Err(err) if err.is_transient() => return self.idle(io, err).await,
+                Err(err) => return Err(err),
+            },
+        };
+        if let Err(err) = validate(&kafka_message) {
+            return self.dead_letter(io, kafka_message, err).await;
+        }
+
+        //  Get Redis config
+        let redis_config = match self.read_redis_config(io).await {
+            Ok(config) => config,
+            Err(err) if err.is_transient() => {
+                self.pending_message = Some(kafka_message);
+                return self.idle(io, err).await;
+            }
+            Err(err) => return Err(err),
+        };
+
+        //  Records must reach the file in offset order, so the new record queues up behind
+        //  anything not written yet and goes out with the rest of its batch
+        if self.queue.is_empty() {
+            self.batch_started = Some(io.now());
+        }
+        self.queue
+            .push(OutputRecord::new(&kafka_message, redis_config));
+        self.status = vec![
+            "Read messages from Kafka".to_string(),
+            self.config_status.clone(),
+        ];
+        if self.batch_due(io.now()) {
+            self.flush(io).await;
+        }
+        if self.queue.is_empty() && self.unsynced.is_empty() {
+            self.status.push("Wrote output to file".to_string());
+        } else if !self.disk_full {
+            self.status.push(format!(
+                "Batched {} records",
+                self.queue.len() + self.unsynced.len()
+            ));
+        } else {
+            self.status
+                .push("ALERT: Disk full, pausing consumption".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Workload for PipelineWorkload {
+    async fn init(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        let mut retry = self.connect_retry.start(io);
+        loop {
+            match io
+                .create_kafka_consumer("group_id", "localhost:9092", "dummy_topic", 0)
+                .await
+            {
+                Ok(_) => break,
+                Err(err) => retry.backoff(io, err).await?,
+            }
+        }
+
+        let mut retry = self.connect_retry.start(io);
+        loop {
+            match io.connect_to_redis("redis://127.0.0.1").await {
+                Ok(_) => break,
+                Err(err) => retry.backoff(io, err).await?,
+            }
+        }
+
+        io.open_file(Path::new("output.txt"), self.rotation.clone())
+            .await?;
+        self.sink.recover(io).await?;
+        self.queue.recover(io).await?;
+        if !self.queue.is_empty() {
+            self.batch_started = Some(io.now());
+        }
+        self.status = vec![
+            "Connected to Kafka".to_string(),
+            "Connected to Redis".to_string(),
+            "Opened file descriptor".to_string(),
+        ];
+        Ok(())
+    }
+
+    async fn step(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        self.refresh_config(io).await;
+        let result = self.consume(io).await;
+        self.kafka_breaker.update(io.now());
+        self.redis_breaker.update(io.now());
+        if let Some(metrics) = io.metrics() {
+            metrics.set_gauge("write_queue_depth", self.queue.len() as f64);
+            metrics.set_gauge("spilled_writes", self.queue.pending_spilled() as f64);
+            metrics.set_gauge("unacknowledged_writes", self.unsynced.len() as f64);
+        }
+        result
+    }
+
+    async fn check(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        //  Every few iterations, make sure the tail of the file matches what we think we wrote
+        if self.written_messages.len() < 5 || !self.counter.is_multiple_of(5) {
+            return Ok(());
+        }
+        let read_messages = io.read_last_n_entries(5).await?;
+        let expected = &self.written_messages[self.written_messages.len() - 5..];
+        if read_messages != expected {
+            return Err(Errors::new(ErrorKind::ExpectedFileReadError)
+                .with_operation("read_last_n_entries")
+                .with_source(format!(
+                    "expected {:?} but read {:?}",
+                    expected, read_messages
+                )));
+        }
+        Ok(())
+    }
+
+    /// Everything written this run must be in the file, in order, and the file as a whole
+    /// must hold each offset of a partition exactly once, without gaps other than the
+    /// offsets that were dead-lettered.
+    async fn verify(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        let entries = io.read_last_n_entries(usize::MAX).await?;
+        let verify_error = |source: String| {
+            Errors::new(ErrorKind::ExpectedFileReadError)
+                .with_operation("verify")
+                .with_source(source)
+        };
+        let tail = &entries[entries.len().saturating_sub(self.written_messages.len())..];
+        if tail != self.written_messages.as_slice() {
+            return Err(verify_error(format!(
+                "wrote {} records but the file ends with {} different ones",
+                self.written_messages.len(),
+                tail.len()
+            )));
+        }
+        let dead_lettered: HashSet<(String, i32, i64)> = io
+            .read_records(&self.dead_letter_path)
+            .await?
+            .iter()
+            .filter_map(|line| serde_json::from_str::<KafkaMessage>(line).ok())
+            .map(|message| (message.topic, message.partition, message.offset))
+            .collect();
+        let mut last_offsets = HashMap::new();
+        for record in entries.iter().filter_map(|line| OutputRecord::decode(line)) {
+            let last = last_offsets.insert((record.topic.clone(), record.partition), record.offset);
+            let skipped_dead_letters = |last: i64| {
+                (last + 1..record.offset).all(|offset| {
+                    dead_lettered.contains(&(record.topic.clone(), record.partition, offset))
+                })
+            };
+            if let Some(last) =
+                last.filter(|&last| record.offset <= last || !skipped_dead_letters(last))
+            {
+                return Err(verify_error(format!(
+                    "offset {} of {}/{} follows offset {}",
+                    record.offset, record.topic, record.partition, last
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        info!(
+            "draining {} queued and {} unacknowledged records",
+            self.queue.len(),
+            self.unsynced.len()
+        );
+        //  A message parked mid-step was never committed, so Kafka hands it out again
+        self.pending_message = None;
+        for _ in 0..SHUTDOWN_FLUSH_ATTEMPTS {
+            self.flush(io).await;
+            if self.queue.is_empty() && self.unsynced.is_empty() {
+                self.status = vec!["Shut down cleanly".to_string()];
+                return Ok(());
+            }
+            io.sleep(self.idle_backoff).await;
+        }
+        self.status = vec![format!(
+            "Shut down with {} records unwritten",
+            self.queue.len() + self.unsynced.len()
+        )];
+        Err(Errors::new(ErrorKind::ShutdownIncomplete)
+            .with_operation("shutdown")
+            .with_source(format!(
+                "{} records queued, {} written but not acknowledged",
+                self.queue.len(),
+                self.unsynced.len()
+            )))
+    }
+
+    fn status_messages(&self) -> Vec<String> {
+        self.status.clone()
+    }
+
+    fn circuit_breakers(&self) -> Vec<&CircuitBreaker> {
+        vec![&self.kafka_breaker, &self.redis_breaker]
+    }
+}
+
+/// A trivial business validation, standing in for whatever makes a message impossible to
+/// process. Retrying can't fix it, so the message is dead-lettered.
+fn validate(message: &KafkaMessage) -> Result<(), Errors> {
+    if message.payload.len() < MIN_PAYLOAD_LEN {
+        return Err(Errors::new(ErrorKind::InvalidKafkaMessage)
+            .with_operation("read_kafka_message")
+            .with_source(format!("message shorter than {} bytes", MIN_PAYLOAD_LEN)));
+    }
+    Ok(())
+}
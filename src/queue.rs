@@ -0,0 +1,249 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use tracing::{info, warn};
+
+use crate::segment::segment_directory;
+use crate::{Errors, OutputRecord, IO};
+
+/// Output records waiting to be written, oldest first.
+///
+/// At most `capacity` records are held in memory. Past that the caller either spills them
+/// to a durable side file, if one is configured, or stops consuming until the queue drains.
+/// Spilled records are always older than the ones in memory, so writing the spill file out
+/// before memory keeps offset order.
+pub struct WriteQueue {
+    capacity: usize,
+    spill_path: Option<PathBuf>,
+    memory: VecDeque<OutputRecord>,
+    spilled: usize,
+    /// The spilled records have reached the output, the spill file just hasn't been deleted.
+    spill_written: bool,
+}
+
+impl Default for WriteQueue {
+    /// Up to 1024 records in memory, without a spill file.
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl WriteQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            spill_path: None,
+            memory: VecDeque::new(),
+            spilled: 0,
+            spill_written: false,
+        }
+    }
+
+    /// Spills records that don't fit in memory to `path` instead of pausing consumption.
+    pub fn with_spill_file(mut self, path: &Path) -> Self {
+        self.spill_path = Some(path.to_path_buf());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending_spilled() + self.memory.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Records sitting in the spill file that still have to be written out.
+    pub fn pending_spilled(&self) -> usize {
+        if self.spill_written {
+            0
+        } else {
+            self.spilled
+        }
+    }
+
+    /// Whether memory is at capacity, so nothing more should be pushed until it is spilled
+    /// or written out.
+    pub fn is_full(&self) -> bool {
+        self.memory.len() >= self.capacity
+    }
+
+    pub fn can_spill(&self) -> bool {
+        self.spill_path.is_some()
+    }
+
+    pub fn push(&mut self, record: OutputRecord) {
+        self.memory.push_back(record);
+    }
+
+    /// Picks up records spilled before a restart.
+    pub async fn recover(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        let Some(path) = &self.spill_path else {
+            return Ok(());
+        };
+        self.spilled = io.read_records(path).await?.len();
+        self.spill_written = false;
+        if self.spilled > 0 {
+            info!("recovered {} spilled records", self.spilled);
+        }
+        Ok(())
+    }
+
+    /// Moves every record held in memory to the end of the spill file and makes it durable.
+    /// Returns the newest record spilled, whose offset can now be committed.
+    pub async fn spill(&mut self, io: &mut dyn IO) -> Result<Option<OutputRecord>, Errors> {
+        let Some(path) = self.spill_path.clone() else {
+            return Ok(None);
+        };
+        if self.memory.is_empty() {
+            return Ok(None);
+        }
+        //  Spilled records that already reached the output must not be written out again
+        //  along with the new ones
+        self.release_spill(io).await?;
+        let encoded: Vec<String> = self.memory.iter().map(OutputRecord::encode).collect();
+        io.write_records(&path, &encoded).await?;
+        io.sync_dir(segment_directory(&path)).await?;
+        warn!(
+            "spilled {} records to {}, {} spilled in total",
+            encoded.len(),
+            path.display(),
+            self.spilled + encoded.len()
+        );
+        self.spilled += encoded.len();
+        let newest = self.memory.back().cloned();
+        self.memory.clear();
+        Ok(newest)
+    }
+
+    /// Every record still to be written, oldest first.
+    pub async fn pending(&self, io: &mut dyn IO) -> Result<Vec<OutputRecord>, Errors> {
+        let mut records = Vec::with_capacity(self.len());
+        if let (Some(path), true) = (&self.spill_path, self.pending_spilled() > 0) {
+            let spilled = io.read_records(path).await?;
+            records.extend(spilled.iter().filter_map(|line| OutputRecord::decode(line)));
+        }
+        records.extend(self.memory.iter().cloned());
+        Ok(records)
+    }
+
+    /// Everything `pending` returned has been written to the output.
+    pub fn written(&mut self) {
+        self.memory.clear();
+        if self.spilled > 0 {
+            self.spill_written = true;
+        }
+    }
+
+    /// Deletes the spill file once its records are durable in the output.
+    pub async fn release_spill(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        let Some(path) = &self.spill_path else {
+            return Ok(());
+        };
+        if !self.spill_written {
+            return Ok(());
+        }
+        io.remove_file(path).await?;
+        io.sync_dir(segment_directory(path)).await?;
+        self.spilled = 0;
+        self.spill_written = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KafkaMessage, SimulatedIO, SimulationControl};
+
+    fn record(offset: i64) -> OutputRecord {
+        let message = KafkaMessage {
+            topic: "topic".to_string(),
+            partition: 0,
+            offset,
+            payload: format!("message {}", offset),
+        };
+        OutputRecord::new(&message, "config".to_string())
+    }
+
+    fn offsets(records: &[OutputRecord]) -> Vec<i64> {
+        records.iter().map(|record| record.offset).collect()
+    }
+
+    #[tokio::test]
+    async fn without_a_spill_file_a_full_queue_stays_full() {
+        let mut io = SimulatedIO::without_faults(0);
+        let mut queue = WriteQueue::new(2);
+        queue.push(record(0));
+        assert!(!queue.is_full());
+        queue.push(record(1));
+        assert!(queue.is_full());
+        assert!(!queue.can_spill());
+        assert_eq!(queue.spill(&mut io).await.unwrap(), None);
+        assert_eq!(offsets(&queue.pending(&mut io).await.unwrap()), vec![0, 1]);
+
+        queue.written();
+        assert!(queue.is_empty());
+
+        //  Room for at least one record, or nothing could ever be consumed
+        let mut smallest = WriteQueue::new(0);
+        assert!(!smallest.is_full());
+        smallest.push(record(2));
+        assert!(smallest.is_full());
+    }
+
+    #[tokio::test]
+    async fn spilled_records_go_out_before_the_ones_in_memory() {
+        let mut io = SimulatedIO::without_faults(0);
+        let path = Path::new("output.spill");
+        let mut queue = WriteQueue::new(2).with_spill_file(path);
+        queue.push(record(0));
+        queue.push(record(1));
+        assert_eq!(queue.spill(&mut io).await.unwrap(), Some(record(1)));
+        assert!(!queue.is_full());
+        queue.push(record(2));
+        assert_eq!((queue.len(), queue.pending_spilled()), (3, 2));
+        assert_eq!(
+            offsets(&queue.pending(&mut io).await.unwrap()),
+            vec![0, 1, 2]
+        );
+
+        queue.written();
+        assert!(queue.is_empty());
+        assert!(io.file_system().exists(path));
+        queue.release_spill(&mut io).await.unwrap();
+        assert!(!io.file_system().exists(path));
+    }
+
+    #[tokio::test]
+    async fn spilling_again_drops_records_already_written() {
+        let mut io = SimulatedIO::without_faults(0);
+        let path = Path::new("output.spill");
+        let mut queue = WriteQueue::new(1).with_spill_file(path);
+        queue.push(record(0));
+        queue.spill(&mut io).await.unwrap();
+        queue.written();
+        queue.push(record(1));
+        queue.spill(&mut io).await.unwrap();
+        assert_eq!(offsets(&queue.pending(&mut io).await.unwrap()), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn recovers_spilled_records_after_a_restart() {
+        let mut io = SimulatedIO::without_faults(0);
+        let path = Path::new("output.spill");
+        let mut queue = WriteQueue::new(2).with_spill_file(path);
+        queue.push(record(0));
+        queue.push(record(1));
+        queue.spill(&mut io).await.unwrap();
+        io.crash();
+
+        let mut restarted = WriteQueue::new(2).with_spill_file(path);
+        restarted.recover(&mut io).await.unwrap();
+        assert_eq!(restarted.pending_spilled(), 2);
+        assert_eq!(
+            offsets(&restarted.pending(&mut io).await.unwrap()),
+            vec![0, 1]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::stream::StreamExt;
+use rand::Rng;
+use rdkafka::{
+    consumer::{stream_consumer::StreamConsumer, CommitMode, Consumer},
+    ClientConfig, Message, TopicPartitionList,
+};
+use redis::AsyncCommands;
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::segment::{existing_segments, segment_directory};
+use crate::{
+    Clock, ErrorClass, ErrorKind, Errors, File, KafkaMessage, RealClock, RealFile, RotationPolicy,
+    SegmentedLog, IO,
+};
+
+pub struct RealIO {
+    consumer: Option<StreamConsumer>,
+    redis_connection: Option<redis::aio::MultiplexedConnection>,
+    file: Option<SegmentedLog<RealFile>>,
+    shutdown: Arc<AtomicBool>,
+    /// Wakes a Kafka read blocked on an idle topic when a shutdown is requested.
+    shutdown_signal: Arc<Notify>,
+    pub clock: Box<dyn Clock + Send>,
+}
+
+impl RealIO {
+    pub fn new() -> Self {
+        let clock = Box::new(RealClock::new());
+        Self {
+            consumer: None,
+            redis_connection: None,
+            file: None,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            shutdown_signal: Arc::new(Notify::new()),
+            clock,
+        }
+    }
+
+    /// Requests a shutdown on SIGINT or SIGTERM. Must be called inside a tokio runtime.
+    pub fn with_shutdown_signals(self) -> Self {
+        let shutdown = self.shutdown.clone();
+        let shutdown_signal = self.shutdown_signal.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            warn!("shutdown requested, draining before exit");
+            shutdown.store(true, Ordering::SeqCst);
+            shutdown_signal.notify_one();
+        });
+        self
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+impl Default for RealIO {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl IO for RealIO {
+    async fn create_kafka_consumer(
+        &mut self,
+        group_id: &str,
+        broker: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Result<(), Errors> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", group_id)
+            .set("bootstrap.servers", broker)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .map_err(kafka_connection_error)?;
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset(topic, partition, rdkafka::Offset::Stored)
+            .map_err(kafka_connection_error)?;
+        consumer.assign(&tpl).map_err(kafka_connection_error)?;
+
+        self.consumer = Some(consumer);
+        Ok(())
+    }
+
+    async fn connect_to_redis(&mut self, url: &str) -> Result<(), Errors> {
+        let redis_connection_error = |e| {
+            Errors::new(ErrorKind::RedisConnectionError)
+                .with_operation("connect_to_redis")
+                .with_redis_source(e)
+        };
+        let client = redis::Client::open(url).map_err(redis_connection_error)?;
+        let connection = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(redis_connection_error)?;
+        self.redis_connection = Some(connection);
+        Ok(())
+    }
+
+    async fn open_file(&mut self, path: &Path, rotation: RotationPolicy) -> Result<(), Errors> {
+        let mut log = SegmentedLog::new(path, rotation, self.now());
+        for (index, segment) in existing_segments(path).await? {
+            log.push_existing(index, RealFile::open(&segment).await?);
+        }
+        if log.segments().is_empty() {
+            log.push_existing(0, RealFile::open(&log.next_segment_path()).await?);
+        }
+        log.recover().await?;
+        self.file = Some(log);
+        self.sync_dir(segment_directory(path)).await?;
+        Ok(())
+    }
+
+    async fn read_kafka_message(&mut self) -> Result<Option<KafkaMessage>, Errors> {
+        if let Some(consumer) = &self.consumer {
+            if self.shutdown.load(Ordering::SeqCst) {
+                return Ok(None);
+            }
+            let mut stream = consumer.stream();
+            let message = tokio::select! {
+                message = stream.next() => message,
+                //  Give up on the read so the caller gets to notice the shutdown
+                _ = self.shutdown_signal.notified() => return Ok(None),
+            };
+            let msg = match message {
+                Some(Ok(msg)) => msg.payload().map(|payload| KafkaMessage {
+                    topic: msg.topic().to_string(),
+                    partition: msg.partition(),
+                    offset: msg.offset(),
+                    payload: String::from_utf8_lossy(payload).into_owned(),
+                }),
+                Some(Err(e)) => {
+                    return Err(Errors::new(ErrorKind::NoKafkaMessage)
+                        .with_operation("read_kafka_message")
+                        .with_kafka_source(e))
+                }
+                None => {
+                    return Err(Errors::new(ErrorKind::NoKafkaMessage)
+                        .with_operation("read_kafka_message")
+                        .with_source("consumer stream ended"))
+                }
+            };
+            return Ok(msg);
+        }
+        Ok(None)
+    }
+
+    async fn commit_kafka_offset(
+        &mut self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+    ) -> Result<(), Errors> {
+        let commit_error = |e| {
+            Errors::new(ErrorKind::KafkaCommitError)
+                .with_operation("commit_kafka_offset")
+                .with_kafka_source(e)
+        };
+        let Some(consumer) = &self.consumer else {
+            return Err(Errors::new(ErrorKind::KafkaCommitError)
+                .with_operation("commit_kafka_offset")
+                .with_class(ErrorClass::Permanent)
+                .with_source("no Kafka consumer"));
+        };
+        //  Kafka stores the offset of the next message to read
+        let mut tpl = TopicPartitionList::new();
+        tpl.add_partition_offset(topic, partition, rdkafka::Offset::Offset(offset + 1))
+            .map_err(commit_error)?;
+        consumer
+            .commit(&tpl, CommitMode::Sync)
+            .map_err(commit_error)
+    }
+
+    async fn get_redis_config(&mut self, key: &str) -> Result<String, Errors> {
+        if let Some(redis_conn) = &mut self.redis_connection {
+            match redis_conn.get(key).await {
+                Ok(value) => Ok(value),
+                Err(e) => Err(Errors::new(ErrorKind::RedisKeyRetrievalError)
+                    .with_operation("get_redis_config")
+                    .with_redis_source(e)),
+            }
+        } else {
+            Err(Errors::new(ErrorKind::RedisConnectionError)
+                .with_operation("get_redis_config")
+                .with_class(ErrorClass::Permanent)
+                .with_source("not connected to redis"))
+        }
+    }
+
+    async fn read_file(&mut self, size: usize) -> Result<Vec<u8>, Errors> {
+        self.file.as_mut().unwrap().read(size).await
+    }
+
+    async fn write_to_file(&mut self, data: &str) -> Result<usize, Errors> {
+        self.write_batch_to_file(&[data.to_string()]).await
+    }
+
+    async fn write_batch_to_file(&mut self, data: &[String]) -> Result<usize, Errors> {
+        let now = self.now();
+        let log = self.file.as_mut().unwrap();
+        if log.should_rotate(data, now) {
+            let segment = RealFile::open(&log.next_segment_path()).await?;
+            log.rotate(segment, now).await?;
+            //  Otherwise a crash could lose the new segment or bring back a deleted one
+            let directory = segment_directory(log.base()).to_path_buf();
+            self.sync_dir(&directory).await?;
+        }
+        self.file.as_mut().unwrap().write_batch(data).await
+    }
+
+    async fn sync_file(&mut self) -> Result<(), Errors> {
+        self.file.as_mut().unwrap().fsync().await
+    }
+
+    async fn rename_file(&mut self, from: &Path, to: &Path) -> Result<(), Errors> {
+        tokio::fs::rename(from, to).await.map_err(|e| {
+            Errors::new(ErrorKind::FileWriteError)
+                .with_operation("rename_file")
+                .with_io_source(e)
+        })
+    }
+
+    async fn sync_dir(&mut self, directory: &Path) -> Result<(), Errors> {
+        let sync_error = |e| {
+            Errors::new(ErrorKind::FileSyncError)
+                .with_operation("sync_dir")
+                .with_io_source(e)
+        };
+        let directory = tokio::fs::File::open(directory).await.map_err(sync_error)?;
+        directory.sync_all().await.map_err(sync_error)
+    }
+
+    async fn write_records(&mut self, path: &Path, records: &[String]) -> Result<(), Errors> {
+        let mut file = RealFile::open(path).await?;
+        file.recover().await?;
+        file.write_batch(records).await?;
+        file.fsync().await
+    }
+
+    async fn read_records(&mut self, path: &Path) -> Result<Vec<String>, Errors> {
+        if !tokio::fs::try_exists(path).await.unwrap_or(false) {
+            return Ok(Vec::new());
+        }
+        let mut file = RealFile::open(path).await?;
+        file.recover().await?;
+        file.read_last_n_entries(usize::MAX).await
+    }
+
+    async fn remove_file(&mut self, path: &Path) -> Result<(), Errors> {
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(Errors::new(ErrorKind::FileWriteError)
+                    .with_operation("remove_file")
+                    .with_io_source(e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn read_last_n_entries(&mut self, n: usize) -> Result<Vec<String>, Errors> {
+        self.file.as_mut().unwrap().read_last_n_entries(n).await
+    }
+
+    fn generate_jitter(&mut self, base_delay: Duration) -> Duration {
+        let jitter: u64 = rand::thread_rng().gen_range(0..base_delay.as_millis() as u64);
+        base_delay + Duration::from_millis(jitter)
+    }
+
+    async fn sleep(&mut self, duration: Duration) {
+        self.clock.sleep(duration).await;
+    }
+
+    fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+}
+
+fn kafka_connection_error(e: rdkafka::error::KafkaError) -> Errors {
+    Errors::new(ErrorKind::KafkaConnectionError)
+        .with_operation("create_kafka_consumer")
+        .with_kafka_source(e)
+}
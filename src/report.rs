@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::simulator::{Phase, SimulationOutcome};
+use crate::{Errors, FaultType};
+
+/// Machine-readable account of one simulated run, for aggregating results across sweeps.
+#[derive(Debug, Serialize)]
+pub struct SimulationReport {
+    pub seed: u64,
+    pub passed: bool,
+    pub steps: usize,
+    pub virtual_time_ms: u128,
+    /// Probability of each fault when the run started.
+    pub fault_profile: BTreeMap<String, f64>,
+    pub faults: Vec<FaultReport>,
+    /// Retried attempts, by operation.
+    pub retries: BTreeMap<String, u64>,
+    pub messages: MessageCounts,
+    pub invariants: Vec<InvariantReport>,
+    pub error: Option<ErrorReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FaultReport {
+    pub fault: String,
+    pub step: usize,
+    pub virtual_time_ms: u128,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MessageCounts {
+    pub consumed: u64,
+    pub written: u64,
+    /// Records whose write failed, counted once per failed attempt.
+    pub failed: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvariantStatus {
+    Pass,
+    Fail,
+    /// The run failed before this invariant was checked.
+    Skipped,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvariantReport {
+    /// `check` runs after every step, `verify` once at the end.
+    pub name: &'static str,
+    pub status: InvariantStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub phase: String,
+    pub kind: String,
+    pub class: String,
+    pub operation: Option<&'static str>,
+    pub attempt: Option<u32>,
+    pub message: String,
+}
+
+fn fault_name(fault: &FaultType) -> String {
+    match fault {
+        FaultType::FileFaultType(file_fault) => format!("{:?}", file_fault),
+        _ => format!("{:?}", fault),
+    }
+}
+
+/// `check` runs throughout the step loop, so it only goes unchecked when `init` fails.
+/// `verify` only runs once everything before it succeeded.
+fn invariants(failed_phase: Option<Phase>) -> Vec<InvariantReport> {
+    let status = |phase: Phase, ran: bool| match failed_phase {
+        Some(failed) if failed == phase => InvariantStatus::Fail,
+        _ if ran => InvariantStatus::Pass,
+        _ => InvariantStatus::Skipped,
+    };
+    vec![
+        InvariantReport {
+            name: "check",
+            status: status(Phase::Check, failed_phase != Some(Phase::Init)),
+        },
+        InvariantReport {
+            name: "verify",
+            status: status(Phase::Verify, failed_phase.is_none()),
+        },
+    ]
+}
+
+impl ErrorReport {
+    fn new(phase: Phase, error: &Errors) -> Self {
+        Self {
+            phase: format!("{:?}", phase).to_lowercase(),
+            kind: format!("{:?}", error.kind()),
+            class: format!("{:?}", error.class()),
+            operation: error.operation(),
+            attempt: error.attempt(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl SimulationReport {
+    pub fn new(outcome: &SimulationOutcome) -> Self {
+        let metrics = &outcome.metrics;
+        Self {
+            seed: outcome.seed,
+            passed: outcome.passed(),
+            steps: outcome.steps,
+            virtual_time_ms: outcome.elapsed.as_millis(),
+            fault_profile: outcome
+                .fault_profile
+                .iter()
+                .map(|(fault, probability)| (fault_name(fault), *probability))
+                .collect(),
+            faults: outcome
+                .faults
+                .iter()
+                .map(|injected| FaultReport {
+                    fault: fault_name(&injected.fault),
+                    step: injected.step,
+                    virtual_time_ms: injected.at.as_millis(),
+                })
+                .collect(),
+            retries: metrics.retries(),
+            messages: MessageCounts {
+                consumed: metrics.counter("messages_consumed"),
+                written: metrics.counter("messages_written"),
+                failed: metrics.counter("messages_failed"),
+            },
+            invariants: invariants(outcome.failed_phase),
+            error: outcome
+                .failed_phase
+                .zip(outcome.error.as_ref())
+                .map(|(phase, error)| ErrorReport::new(phase, error)),
+        }
+    }
+}
+
+/// Writes `reports` to `path` as a JSON array.
+pub fn write_reports(path: &Path, reports: &[SimulationReport]) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(reports).map_err(std::io::Error::other)?;
+    std::fs::write(path, json)
+}
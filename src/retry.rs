@@ -0,0 +1,272 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{error, warn};
+
+use crate::{ErrorClass, ErrorKind, Errors, IO};
+
+/// How long to wait between attempts.
+#[derive(Clone, Debug)]
+pub enum Backoff {
+    /// `base * multiplier^n` plus up to the same amount again of jitter, capped at `max`.
+    Exponential {
+        base: Duration,
+        multiplier: u32,
+        max: Duration,
+    },
+    /// Each delay is drawn between `base` and three times the previous delay, capped at `max`.
+    /// Spreads retries from many callers apart better than plain exponential backoff.
+    DecorrelatedJitter { base: Duration, max: Duration },
+}
+
+/// Decides whether and when a failed operation is retried.
+///
+/// All waiting goes through `IO::sleep` and all randomness through `IO::generate_jitter`,
+/// so a policy behaves the same in real and simulated runs and is deterministic under a seed.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Backoff,
+    max_elapsed: Option<Duration>,
+    retry_on: Arc<dyn Fn(&Errors) -> bool + Send + Sync>,
+}
+
+impl Default for RetryPolicy {
+    /// Five retries with exponential backoff from 10ms, only for transient errors. Calls
+    /// rejected by an open circuit breaker aren't retried, the breaker already knows better.
+    fn default() -> Self {
+        Self {
+            max_attempts: 6,
+            backoff: Backoff::Exponential {
+                base: Duration::from_millis(10),
+                multiplier: 2,
+                max: Duration::from_secs(5),
+            },
+            max_elapsed: None,
+            retry_on: Arc::new(|err: &Errors| {
+                err.is_transient() && err.kind() != ErrorKind::CircuitOpen
+            }),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that gives up on the first failure.
+    pub fn never() -> Self {
+        Self::default().with_max_attempts(1)
+    }
+
+    /// Total number of attempts, including the first one.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Stops retrying once this much time (virtual when simulated) has passed since the
+    /// first attempt, even if attempts remain.
+    pub fn with_max_elapsed(mut self, max_elapsed: Duration) -> Self {
+        self.max_elapsed = Some(max_elapsed);
+        self
+    }
+
+    /// Only errors for which `predicate` returns true are retried.
+    pub fn retry_on(mut self, predicate: impl Fn(&Errors) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_on = Arc::new(predicate);
+        self
+    }
+
+    /// Retries errors of the given classes only.
+    pub fn retry_on_classes(self, classes: &[ErrorClass]) -> Self {
+        let classes = classes.to_vec();
+        self.retry_on(move |err| classes.contains(&err.class()))
+    }
+
+    /// Begins tracking attempts for one operation.
+    pub fn start(&self, io: &dyn IO) -> Retry<'_> {
+        Retry {
+            policy: self,
+            attempt: 0,
+            started_at: io.now(),
+            previous_delay: Duration::ZERO,
+        }
+    }
+
+    fn next_delay(&self, io: &mut dyn IO, attempt: u32, previous: Duration) -> Duration {
+        match self.backoff {
+            Backoff::Exponential {
+                base,
+                multiplier,
+                max,
+            } => {
+                let delay = base.saturating_mul(multiplier.saturating_pow(attempt - 1));
+                jitter(io, delay).min(max)
+            }
+            Backoff::DecorrelatedJitter { base, max } => {
+                let span = previous.saturating_mul(3).saturating_sub(base);
+                let extra = jitter(io, span).saturating_sub(span);
+                (base + extra).min(max)
+            }
+        }
+    }
+}
+
+/// `generate_jitter` can't work with less than a millisecond to play with.
+fn jitter(io: &mut dyn IO, delay: Duration) -> Duration {
+    if delay.as_millis() == 0 {
+        delay
+    } else {
+        io.generate_jitter(delay)
+    }
+}
+
+/// Attempt bookkeeping for a single operation under a `RetryPolicy`.
+///
+/// ```ignore
+/// let mut retry = policy.start(io);
+/// let value = loop {
+///     match io.get_redis_config(key).await {
+///         Ok(value) => break value,
+///         Err(err) => retry.backoff(io, err).await?,
+///     }
+/// };
+/// ```
+pub struct Retry<'a> {
+    policy: &'a RetryPolicy,
+    attempt: u32,
+    started_at: Duration,
+    previous_delay: Duration,
+}
+
+impl Retry<'_> {
+    /// Records a failed attempt. Sleeps and returns `Ok` when the operation should be tried
+    /// again, otherwise returns the error tagged with the attempt that produced it.
+    pub async fn backoff(&mut self, io: &mut dyn IO, err: Errors) -> Result<(), Errors> {
+        self.attempt += 1;
+        let err = err.with_attempt(self.attempt);
+        if !(self.policy.retry_on)(&err) || self.attempt >= self.policy.max_attempts {
+            error!("giving up: {}", err);
+            return Err(err);
+        }
+        let delay = self
+            .policy
+            .next_delay(io, self.attempt, self.previous_delay);
+        if let Some(max_elapsed) = self.policy.max_elapsed {
+            if io.now().saturating_sub(self.started_at) + delay > max_elapsed {
+                error!("giving up after {:?}: {}", max_elapsed, err);
+                return Err(err);
+            }
+        }
+        warn!("{}, retrying in {:?}", err, delay);
+        if let Some(metrics) = io.metrics() {
+            metrics.record_retry(&err);
+        }
+        self.previous_delay = delay;
+        io.sleep(delay).await;
+        Ok(())
+    }
+
+    /// Number of failed attempts so far.
+    pub fn attempts(&self) -> u32 {
+        self.attempt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimulatedIO;
+
+    fn transient() -> Errors {
+        Errors::new(ErrorKind::RedisConnectionError)
+    }
+
+    /// Fails every attempt under `policy`, returning the final error and the virtual time
+    /// spent waiting between attempts.
+    async fn exhaust(policy: &RetryPolicy, err: Errors) -> (Errors, Duration) {
+        let mut io = SimulatedIO::new(7);
+        let started = io.now();
+        let mut retry = policy.start(&io);
+        loop {
+            if let Err(err) = retry.backoff(&mut io, err.clone()).await {
+                return (err, io.now() - started);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let policy = RetryPolicy::default().with_max_attempts(4);
+        let (err, _) = exhaust(&policy, transient()).await;
+        assert_eq!(err.attempt(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn never_retries_permanent_errors_or_open_circuits() {
+        let permanent = transient().with_class(ErrorClass::Permanent);
+        let (err, waited) = exhaust(&RetryPolicy::default(), permanent).await;
+        assert_eq!(err.attempt(), Some(1));
+        assert_eq!(waited, Duration::ZERO);
+
+        let open = Errors::new(ErrorKind::CircuitOpen);
+        let (err, _) = exhaust(&RetryPolicy::default(), open).await;
+        assert_eq!(err.attempt(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn retries_only_the_chosen_classes() {
+        let policy = RetryPolicy::default()
+            .with_max_attempts(3)
+            .retry_on_classes(&[ErrorClass::Permanent]);
+        let (err, _) = exhaust(&policy, transient()).await;
+        assert_eq!(err.attempt(), Some(1));
+        let (err, _) = exhaust(&policy, transient().with_class(ErrorClass::Permanent)).await;
+        assert_eq!(err.attempt(), Some(3));
+    }
+
+    #[tokio::test]
+    async fn exponential_backoff_doubles_with_jitter_up_to_the_cap() {
+        let policy =
+            RetryPolicy::default()
+                .with_max_attempts(4)
+                .with_backoff(Backoff::Exponential {
+                    base: Duration::from_millis(10),
+                    multiplier: 2,
+                    max: Duration::from_millis(30),
+                });
+        //  10-20ms, then 20-40ms and 40-80ms, both capped at 30ms
+        let (_, waited) = exhaust(&policy, transient()).await;
+        assert!(
+            waited >= Duration::from_millis(10 + 20 + 30),
+            "{:?}",
+            waited
+        );
+        assert!(waited < Duration::from_millis(20 + 30 + 30), "{:?}", waited);
+    }
+
+    #[tokio::test]
+    async fn decorrelated_jitter_stays_between_base_and_max() {
+        let base = Duration::from_millis(10);
+        let max = Duration::from_millis(50);
+        let policy = RetryPolicy::default()
+            .with_max_attempts(20)
+            .with_backoff(Backoff::DecorrelatedJitter { base, max });
+        let (_, waited) = exhaust(&policy, transient()).await;
+        assert!(waited >= base * 19, "{:?}", waited);
+        assert!(waited <= max * 19, "{:?}", waited);
+    }
+
+    #[tokio::test]
+    async fn stops_once_max_elapsed_would_be_exceeded() {
+        let policy = RetryPolicy::default()
+            .with_max_attempts(100)
+            .with_max_elapsed(Duration::from_millis(100));
+        let (err, waited) = exhaust(&policy, transient()).await;
+        assert!(waited <= Duration::from_millis(100), "{:?}", waited);
+        assert!(err.attempt() < Some(100));
+    }
+}
@@ -0,0 +1,373 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::info;
+
+use crate::{frame, ErrorClass, ErrorKind, Errors, File};
+
+/// When the active segment of a `SegmentedLog` is sealed and a new one started.
+#[derive(Clone, Debug)]
+pub struct RotationPolicy {
+    max_segment_size: usize,
+    max_segment_age: Option<Duration>,
+    max_segments: Option<usize>,
+}
+
+impl Default for RotationPolicy {
+    /// Segments of up to 64MB, with no age limit, kept forever.
+    fn default() -> Self {
+        Self {
+            max_segment_size: 64 * 1024 * 1024,
+            max_segment_age: None,
+            max_segments: None,
+        }
+    }
+}
+
+impl RotationPolicy {
+    /// Rotates before a write would take the active segment past `bytes`, framing included.
+    pub fn with_max_segment_size(mut self, bytes: usize) -> Self {
+        self.max_segment_size = bytes;
+        self
+    }
+
+    /// Rotates once the active segment has been open for `age`, measured with `IO::now`.
+    pub fn with_max_segment_age(mut self, age: Duration) -> Self {
+        self.max_segment_age = Some(age);
+        self
+    }
+
+    /// Deletes the oldest segments on rotation so that at most `count` are kept.
+    pub fn with_max_segments(mut self, count: usize) -> Self {
+        self.max_segments = Some(count.max(1));
+        self
+    }
+}
+
+/// `output.txt` -> `output.000003.txt`
+pub fn segment_path(base: &Path, index: usize) -> PathBuf {
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match base.extension() {
+        Some(extension) => format!("{}.{:06}.{}", stem, index, extension.to_string_lossy()),
+        None => format!("{}.{:06}", stem, index),
+    };
+    base.with_file_name(name)
+}
+
+fn segment_index(base: &Path, path: &Path) -> Option<usize> {
+    let stem = base.file_stem()?.to_string_lossy();
+    let name = path.file_name()?.to_string_lossy();
+    let rest = name.strip_prefix(&*stem)?.strip_prefix('.')?;
+    let index = match base.extension() {
+        Some(extension) => rest
+            .strip_suffix(&*extension.to_string_lossy())?
+            .strip_suffix('.')?,
+        None => rest,
+    };
+    index.parse().ok()
+}
+
+/// The directory `base` and its segments live in.
+pub fn segment_directory(base: &Path) -> &Path {
+    match base.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Picks the segments of `base` out of `paths`, returning their indexes and paths oldest
+/// first.
+pub fn segments_in(base: &Path, paths: impl IntoIterator<Item = PathBuf>) -> Vec<(usize, PathBuf)> {
+    let mut segments: Vec<(usize, PathBuf)> = paths
+        .into_iter()
+        .filter_map(|path| {
+            segment_index(base, &path).map(|index| (index, segment_path(base, index)))
+        })
+        .collect();
+    segments.sort();
+    segments
+}
+
+/// Indexes and paths of the segments of `base` already on disk, oldest first.
+pub async fn existing_segments(base: &Path) -> Result<Vec<(usize, PathBuf)>, Errors> {
+    let read_error = |e| {
+        Errors::new(ErrorKind::FileOpenError)
+            .with_operation("open_file")
+            .with_io_source(e)
+    };
+    let mut paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(segment_directory(base))
+        .await
+        .map_err(read_error)?;
+    while let Some(entry) = entries.next_entry().await.map_err(read_error)? {
+        paths.push(entry.path());
+    }
+    Ok(segments_in(base, paths))
+}
+
+/// An output log split across numbered segment files. Writes go to the last segment, which
+/// is synced and closed once the rotation policy says it is full or old enough, and reads
+/// span every segment.
+///
+/// Opening the next segment is up to the IO, so the log only decides when that happens.
+pub struct SegmentedLog<F: File> {
+    base: PathBuf,
+    policy: RotationPolicy,
+    segments: Vec<F>,
+    next_index: usize,
+    active_since: Duration,
+}
+
+impl<F: File + Send> SegmentedLog<F> {
+    pub fn new(base: &Path, policy: RotationPolicy, now: Duration) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            policy,
+            segments: Vec::new(),
+            next_index: 0,
+            active_since: now,
+        }
+    }
+
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    pub fn segments(&self) -> &[F] {
+        &self.segments
+    }
+
+    pub fn segments_mut(&mut self) -> &mut [F] {
+        &mut self.segments
+    }
+
+    pub fn active(&mut self) -> Option<&mut F> {
+        self.segments.last_mut()
+    }
+
+    /// Path of the segment `rotate` expects next.
+    pub fn next_segment_path(&self) -> PathBuf {
+        segment_path(&self.base, self.next_index)
+    }
+
+    /// Adds the segment numbered `index` found on disk. Call `recover` once they have all
+    /// been added.
+    pub fn push_existing(&mut self, index: usize, segment: F) {
+        self.segments.push(segment);
+        self.next_index = self.next_index.max(index + 1);
+    }
+
+    /// Whether writing `data` at `now` has to go to a new segment. An empty segment always
+    /// takes the write, so a batch larger than the limit still fits somewhere.
+    pub fn should_rotate(&self, data: &[String], now: Duration) -> bool {
+        let Some(active) = self.segments.last() else {
+            return true;
+        };
+        if active.size() == 0 {
+            return false;
+        }
+        let write_len: usize = data
+            .iter()
+            .map(|record| frame::framed_len(record.len()))
+            .sum();
+        let too_big = active.size() + write_len > self.policy.max_segment_size;
+        let too_old = self
+            .policy
+            .max_segment_age
+            .is_some_and(|age| now.saturating_sub(self.active_since) >= age);
+        too_big || too_old
+    }
+
+    /// Seals the active segment and makes `segment`, opened at `next_segment_path`, the one
+    /// written to. Segments beyond the retention limit are deleted, oldest first.
+    pub async fn rotate(&mut self, segment: F, now: Duration) -> Result<(), Errors> {
+        if let Some(active) = self.segments.last_mut() {
+            active.close().await?;
+        }
+        info!(
+            "rotated to segment {} of {}",
+            self.next_index,
+            self.base.display()
+        );
+        self.segments.push(segment);
+        self.next_index += 1;
+        self.active_since = now;
+
+        let retained = self.policy.max_segments.unwrap_or(usize::MAX);
+        while self.segments.len() > retained {
+            let mut oldest = self.segments.remove(0);
+            oldest.remove().await?;
+            info!(
+                "deleted the oldest segment of {}, {} left",
+                self.base.display(),
+                self.segments.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Truncates torn tails from every segment and closes all but the last. Returns how many
+    /// bytes were cut off in total.
+    pub async fn recover(&mut self) -> Result<usize, Errors> {
+        let mut truncated = 0;
+        let sealed = self.segments.len().saturating_sub(1);
+        for (index, segment) in self.segments.iter_mut().enumerate() {
+            truncated += segment.recover().await?;
+            if index < sealed {
+                segment.close().await?;
+            }
+        }
+        Ok(truncated)
+    }
+
+    pub async fn read(&mut self, size: usize) -> Result<Vec<u8>, Errors> {
+        self.active_segment("read")?.read(size).await
+    }
+
+    pub async fn write_batch(&mut self, data: &[String]) -> Result<usize, Errors> {
+        self.active_segment("write")?.write_batch(data).await
+    }
+
+    pub async fn fsync(&mut self) -> Result<(), Errors> {
+        self.active_segment("fsync")?.fsync().await
+    }
+
+    /// The last `n` records, walking back through older segments as needed.
+    pub async fn read_last_n_entries(&mut self, n: usize) -> Result<Vec<String>, Errors> {
+        let mut entries = Vec::new();
+        for segment in self.segments.iter_mut().rev() {
+            if entries.len() >= n {
+                break;
+            }
+            let mut older = segment.read_last_n_entries(n - entries.len()).await?;
+            older.append(&mut entries);
+            entries = older;
+        }
+        Ok(entries)
+    }
+
+    fn active_segment(&mut self, operation: &'static str) -> Result<&mut F, Errors> {
+        self.segments.last_mut().ok_or_else(|| {
+            Errors::new(ErrorKind::FileOpenError)
+                .with_operation(operation)
+                .with_class(ErrorClass::Permanent)
+                .with_source("no segment is open")
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimulatedIO, SimulationControl};
+
+    fn records(count: usize) -> Vec<String> {
+        (0..count).map(|i| format!("record {}", i)).collect()
+    }
+
+    #[test]
+    fn segment_paths_round_trip() {
+        let base = Path::new("logs/output.txt");
+        assert_eq!(segment_path(base, 3), Path::new("logs/output.000003.txt"));
+        assert_eq!(segment_index(base, &segment_path(base, 42)), Some(42));
+        assert_eq!(
+            segment_path(Path::new("output"), 1),
+            Path::new("output.000001")
+        );
+        assert_eq!(segment_directory(base), Path::new("logs"));
+        assert_eq!(segment_directory(Path::new("output.txt")), Path::new("."));
+    }
+
+    #[test]
+    fn segments_in_skips_other_files_and_sorts() {
+        let base = Path::new("output.txt");
+        let listed = [
+            "output.000010.txt",
+            "output.spill",
+            "output.000002.txt",
+            "other.000001.txt",
+            "output.txt",
+            "output.x.txt",
+        ]
+        .map(PathBuf::from);
+        assert_eq!(
+            segments_in(base, listed),
+            vec![
+                (2, PathBuf::from("output.000002.txt")),
+                (10, PathBuf::from("output.000010.txt")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rotates_by_size_and_age() {
+        let mut io = SimulatedIO::without_faults(0);
+        let policy = RotationPolicy::default()
+            .with_max_segment_size(frame::framed_len(8) * 2)
+            .with_max_segment_age(Duration::from_secs(10));
+        let mut log = SegmentedLog::new(Path::new("output.txt"), policy, Duration::ZERO);
+        assert!(log.should_rotate(&records(1), Duration::ZERO));
+        let segment = io
+            .open_simulated_file(&log.next_segment_path())
+            .await
+            .unwrap();
+        log.rotate(segment, Duration::ZERO).await.unwrap();
+
+        //  An empty segment takes any write, however large
+        assert!(!log.should_rotate(&records(5), Duration::ZERO));
+        log.write_batch(&records(1)).await.unwrap();
+        assert!(!log.should_rotate(&records(1), Duration::from_secs(9)));
+        assert!(log.should_rotate(&records(2), Duration::from_secs(9)));
+        assert!(log.should_rotate(&records(1), Duration::from_secs(10)));
+    }
+
+    #[tokio::test]
+    async fn retention_deletes_the_oldest_segments() {
+        let mut io = SimulatedIO::without_faults(0);
+        let policy = RotationPolicy::default().with_max_segments(2);
+        let mut log = SegmentedLog::new(Path::new("output.txt"), policy, Duration::ZERO);
+        for batch in 0..4 {
+            let segment = io
+                .open_simulated_file(&log.next_segment_path())
+                .await
+                .unwrap();
+            log.rotate(segment, Duration::ZERO).await.unwrap();
+            log.write_batch(&[format!("batch {}", batch)])
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            io.file_system().list(Path::new(".")).unwrap(),
+            vec![
+                PathBuf::from("./output.000002.txt"),
+                PathBuf::from("./output.000003.txt"),
+            ]
+        );
+        assert_eq!(
+            log.read_last_n_entries(usize::MAX).await.unwrap(),
+            vec!["batch 2".to_string(), "batch 3".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_the_last_entries_across_segments() {
+        let mut io = SimulatedIO::without_faults(0);
+        let mut log = SegmentedLog::new(
+            Path::new("output.txt"),
+            RotationPolicy::default(),
+            Duration::ZERO,
+        );
+        let records = records(6);
+        for batch in [&records[..1], &records[1..2], &records[2..3], &records[3..]] {
+            let segment = io
+                .open_simulated_file(&log.next_segment_path())
+                .await
+                .unwrap();
+            log.rotate(segment, Duration::ZERO).await.unwrap();
+            log.write_batch(batch).await.unwrap();
+        }
+        assert_eq!(log.segments().len(), 4);
+        assert_eq!(log.read_last_n_entries(4).await.unwrap(), records[2..]);
+        assert_eq!(log.read_last_n_entries(100).await.unwrap(), records);
+    }
+}
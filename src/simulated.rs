@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+use tracing::{trace, warn};
+
+use crate::segment::{segment_directory, segments_in};
+use crate::{
+    Clock, ErrorClass, ErrorKind, Errors, FaultType, File, FileFaultType, FileSnapshot,
+    KafkaMessage, RotationPolicy, SegmentedLog, SimulatedClock, SimulatedDisk, SimulatedFile,
+    SimulatedFileSystem, SimulationControl, IO,
+};
+
+pub struct SimulatedIO {
+    rng: ChaCha8Rng,
+    fault_probabilities: HashMap<FaultType, f64>,
+    file_fault_probabilities: HashMap<FileFaultType, f64>,
+    kafka_messages: Vec<String>,
+    kafka_attempts: usize,
+    kafka_failures: usize,
+    kafka_topic: String,
+    kafka_partition: i32,
+    kafka_offset: i64,
+    committed_offset: Option<i64>,
+    shutdown_at: Option<Duration>,
+    redis_data: HashMap<String, String>,
+    file: Option<SegmentedLog<SimulatedFile>>,
+    fs: SimulatedFileSystem,
+    clock: SimulatedClock,
+    /// Injected faults along with the virtual time they were injected at.
+    faults_generated: Vec<(FaultType, Duration)>,
+    /// Faults injected by simulated files, not yet moved into `faults_generated`.
+    file_faults: Arc<Mutex<Vec<FileFaultType>>>,
+}
+
+impl SimulatedIO {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let clock = SimulatedClock::new();
+        let kafka_messages = vec![
+            "simulated_message_1".to_string(),
+            "simulated_message_2".to_string(),
+            "simulated_message_3".to_string(),
+        ];
+        let mut redis_data = HashMap::new();
+        redis_data.insert(
+            "config_key".to_string(),
+            "simulated_config_value".to_string(),
+        );
+        let fault_probabilities = HashMap::from([
+            (FaultType::KafkaConnectionFailure, 0.1),
+            (FaultType::KafkaReadFailure, 0.1),
+            (FaultType::RedisConnectionFailure, 0.1),
+            (FaultType::RedisReadFailure, 0.1),
+        ]);
+        let file_fault_probabilities = HashMap::from([
+            (FileFaultType::FileReadFailure, 0.1),
+            (FileFaultType::FileWriteFailure, 0.1),
+            (FileFaultType::FileSizeExceededFailure, 0.1),
+            (FileFaultType::FileMetadataSyncFailure, 0.1),
+        ]);
+        let kafka_failures = rng.gen_range(1..5);
+
+        Self {
+            rng,
+            fault_probabilities,
+            file_fault_probabilities,
+            kafka_messages,
+            redis_data,
+            file: None,
+            fs: SimulatedFileSystem::default(),
+            kafka_attempts: 0,
+            kafka_failures,
+            kafka_topic: String::new(),
+            kafka_partition: 0,
+            kafka_offset: 0,
+            committed_offset: None,
+            shutdown_at: None,
+            clock,
+            faults_generated: Vec::new(),
+            file_faults: Arc::default(),
+        }
+    }
+
+    /// A `SimulatedIO` that injects nothing until a fault's probability is raised.
+    pub fn without_faults(seed: u64) -> Self {
+        let mut io = Self::new(seed);
+        io.disable_faults();
+        io
+    }
+
+    /// Stops injecting every fault, including in files that are already open.
+    pub fn disable_faults(&mut self) {
+        for fault in FaultType::all() {
+            self.set_fault_probability(fault, 0.0);
+        }
+    }
+
+    fn should_inject_fault(&mut self, fault_type: &FaultType) -> bool {
+        if let Some(&probability) = self.fault_probabilities.get(fault_type) {
+            match self.rng.gen_bool(probability) {
+                true => {
+                    let now = self.now();
+                    self.faults_generated.push((fault_type.clone(), now));
+                    true
+                }
+                false => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Opens `path` in the simulated filesystem, injecting faults with the IO's file fault
+    /// probabilities and reporting them to its fault log.
+    pub async fn open_simulated_file(&mut self, path: &Path) -> Result<SimulatedFile, Errors> {
+        //  A fresh stream per handle, so reopening a file doesn't replay the same faults
+        let rng = ChaCha8Rng::seed_from_u64(self.rng.gen());
+        let mut file = SimulatedFile::open(rng, self.fs.clone(), path)?
+            .with_fault_log(self.file_faults.clone());
+        for (fault, &probability) in &self.file_fault_probabilities {
+            file.set_fault_probability(fault.clone(), probability);
+        }
+        Ok(file)
+    }
+
+    /// Records the faults files injected since the last call, at the current virtual time.
+    /// File operations don't move the clock, so this is when they happened.
+    fn collect_file_faults(&mut self) {
+        let now = self.now();
+        let injected = std::mem::take(&mut *self.file_faults.lock().unwrap());
+        self.faults_generated.extend(
+            injected
+                .into_iter()
+                .map(|fault| (FaultType::FileFaultType(fault), now)),
+        );
+    }
+}
+
+#[async_trait]
+impl IO for SimulatedIO {
+    async fn create_kafka_consumer(
+        &mut self,
+        _group_id: &str,
+        _broker: &str,
+        topic: &str,
+        partition: i32,
+    ) -> Result<(), Errors> {
+        self.kafka_attempts += 1;
+        //  Only draw a fault while failures are still allowed, so the fault log never lists
+        //  one that wasn't injected
+        if self.kafka_attempts <= self.kafka_failures
+            && self.should_inject_fault(&FaultType::KafkaConnectionFailure)
+        {
+            warn!("Injecting fault for Kafka connection error");
+            return Err(Errors::new(ErrorKind::KafkaConnectionError)
+                .with_operation("create_kafka_consumer")
+                .with_source("injected fault"));
+        }
+        trace!("Not injecting fault for Kafka connection error");
+        self.sleep(Duration::from_millis(50)).await;
+        self.kafka_topic = topic.to_string();
+        self.kafka_partition = partition;
+        //  Like a real consumer group, pick up after the last committed offset
+        self.kafka_offset = self.committed_offset.map_or(0, |offset| offset + 1);
+        Ok(())
+    }
+
+    async fn connect_to_redis(&mut self, _path: &str) -> Result<(), Errors> {
+        if self.should_inject_fault(&FaultType::RedisConnectionFailure) {
+            warn!("Injecting fault for Redis connection error");
+            return Err(Errors::new(ErrorKind::RedisConnectionError)
+                .with_operation("connect_to_redis")
+                .with_source("injected fault"));
+        }
+        trace!("Not injecting fault for Redis connection error");
+        self.sleep(Duration::from_millis(50)).await;
+        Ok(())
+    }
+
+    async fn open_file(&mut self, path: &Path, rotation: RotationPolicy) -> Result<(), Errors> {
+        let mut log = SegmentedLog::new(path, rotation, self.now());
+        let listed = self.fs.list(segment_directory(path))?;
+        for (index, segment) in segments_in(path, listed) {
+            log.push_existing(index, self.open_simulated_file(&segment).await?);
+        }
+        if log.segments().is_empty() {
+            let segment = self.open_simulated_file(&log.next_segment_path()).await?;
+            log.push_existing(0, segment);
+        }
+        log.recover().await?;
+        self.file = Some(log);
+        self.sync_dir(segment_directory(path)).await?;
+        Ok(())
+    }
+
+    async fn read_kafka_message(&mut self) -> Result<Option<KafkaMessage>, Errors> {
+        //  The connection to the broker can drop at any time, not just while connecting
+        if self.should_inject_fault(&FaultType::KafkaConnectionFailure) {
+            warn!("Injecting fault for Kafka connection error while reading");
+            return Err(Errors::new(ErrorKind::KafkaConnectionError)
+                .with_operation("read_kafka_message")
+                .with_source("injected fault"));
+        }
+        //  Polling takes a little time, so virtual time moves on even when nothing sleeps
+        self.sleep(Duration::from_millis(1)).await;
+        let payload = if self.should_inject_fault(&FaultType::KafkaReadFailure) {
+            //  A malformed message at this offset, which the consumer has to deal with
+            warn!("Injecting fault for Kafka read error");
+            "dummy".to_string()
+        } else {
+            trace!("Not injecting fault for Kafka read error");
+            match self.kafka_messages.choose(&mut self.rng) {
+                Some(message) => message.clone(),
+                None => return Ok(None),
+            }
+        };
+        let message = KafkaMessage {
+            topic: self.kafka_topic.clone(),
+            partition: self.kafka_partition,
+            offset: self.kafka_offset,
+            payload,
+        };
+        self.kafka_offset += 1;
+        Ok(Some(message))
+    }
+
+    async fn commit_kafka_offset(
+        &mut self,
+        _topic: &str,
+        _partition: i32,
+        offset: i64,
+    ) -> Result<(), Errors> {
+        trace!("committing Kafka offset {}", offset);
+        self.committed_offset = Some(offset);
+        Ok(())
+    }
+
+    async fn get_redis_config(&mut self, key: &str) -> Result<String, Errors> {
+        if self.should_inject_fault(&FaultType::RedisReadFailure) {
+            warn!("Injecting fault for Redis read error");
+            return Err(Errors::new(ErrorKind::RedisKeyRetrievalError)
+                .with_operation("get_redis_config")
+                .with_source("injected fault"));
+        }
+        trace!("Not injecting fault for Redis read error");
+        self.sleep(Duration::from_millis(100)).await;
+        self.redis_data.get(key).cloned().ok_or_else(|| {
+            Errors::new(ErrorKind::RedisKeyRetrievalError)
+                .with_operation("get_redis_config")
+                .with_class(ErrorClass::Permanent)
+                .with_source(format!("key {} does not exist", key))
+        })
+    }
+
+    async fn read_file(&mut self, size: usize) -> Result<Vec<u8>, Errors> {
+        let result = self.file.as_mut().unwrap().read(size).await;
+        self.collect_file_faults();
+        result
+    }
+
+    async fn write_to_file(&mut self, data: &str) -> Result<usize, Errors> {
+        self.write_batch_to_file(&[data.to_string()]).await
+    }
+
+    async fn write_batch_to_file(&mut self, data: &[String]) -> Result<usize, Errors> {
+        let now = self.now();
+        let log = self.file.as_ref().unwrap();
+        if log.should_rotate(data, now) {
+            let path = log.next_segment_path();
+            let segment = self.open_simulated_file(&path).await?;
+            self.file.as_mut().unwrap().rotate(segment, now).await?;
+            //  Otherwise a crash could lose the new segment or bring back a deleted one
+            self.sync_dir(segment_directory(&path)).await?;
+        }
+        let result = self.file.as_mut().unwrap().write_batch(data).await;
+        self.collect_file_faults();
+        result
+    }
+
+    async fn sync_file(&mut self) -> Result<(), Errors> {
+        self.file.as_mut().unwrap().fsync().await
+    }
+
+    async fn rename_file(&mut self, from: &Path, to: &Path) -> Result<(), Errors> {
+        self.fs.rename(from, to)
+    }
+
+    async fn sync_dir(&mut self, directory: &Path) -> Result<(), Errors> {
+        self.fs.sync_dir(directory)
+    }
+
+    async fn write_records(&mut self, path: &Path, records: &[String]) -> Result<(), Errors> {
+        let mut file = self.open_simulated_file(path).await?;
+        file.recover().await?;
+        let result = file.write_batch(records).await;
+        self.collect_file_faults();
+        result?;
+        file.fsync().await
+    }
+
+    async fn read_records(&mut self, path: &Path) -> Result<Vec<String>, Errors> {
+        if !self.fs.exists(path) {
+            return Ok(Vec::new());
+        }
+        let mut file = self.open_simulated_file(path).await?;
+        file.recover().await?;
+        file.read_last_n_entries(usize::MAX).await
+    }
+
+    async fn remove_file(&mut self, path: &Path) -> Result<(), Errors> {
+        if !self.fs.exists(path) {
+            return Ok(());
+        }
+        self.fs.unlink(path)
+    }
+
+    async fn read_last_n_entries(&mut self, n: usize) -> Result<Vec<String>, Errors> {
+        self.file.as_mut().unwrap().read_last_n_entries(n).await
+    }
+
+    fn generate_jitter(&mut self, base_delay: Duration) -> Duration {
+        let jitter: u64 = self.rng.gen_range(0..base_delay.as_millis() as u64);
+        base_delay + Duration::from_millis(jitter)
+    }
+
+    async fn sleep(&mut self, duration: Duration) {
+        self.clock.sleep(duration).await;
+    }
+
+    fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        self.shutdown_at.is_some_and(|at| self.now() >= at)
+    }
+
+    fn simulation(&mut self) -> Option<&mut dyn SimulationControl> {
+        Some(self)
+    }
+}
+
+impl SimulationControl for SimulatedIO {
+    fn get_generated_faults(&mut self) -> Vec<FaultType> {
+        self.take_timed_faults()
+            .into_iter()
+            .map(|(fault, _)| fault)
+            .collect()
+    }
+
+    fn take_timed_faults(&mut self) -> Vec<(FaultType, Duration)> {
+        std::mem::take(&mut self.faults_generated)
+    }
+
+    fn advance_clock(&mut self, duration: Duration) {
+        self.clock.advance(duration);
+    }
+
+    fn fault_probability(&self, fault: &FaultType) -> f64 {
+        match fault {
+            FaultType::FileFaultType(file_fault) => self
+                .file_fault_probabilities
+                .get(file_fault)
+                .copied()
+                .unwrap_or(0.0),
+            _ => self.fault_probabilities.get(fault).copied().unwrap_or(0.0),
+        }
+    }
+
+    fn set_fault_probability(&mut self, fault: FaultType, probability: f64) {
+        let probability = probability.clamp(0.0, 1.0);
+        trace!("setting probability of {:?} to {}", fault, probability);
+        match fault {
+            FaultType::FileFaultType(file_fault) => {
+                if let Some(log) = self.file.as_mut() {
+                    for segment in log.segments_mut() {
+                        segment.set_fault_probability(file_fault.clone(), probability);
+                    }
+                }
+                self.file_fault_probabilities
+                    .insert(file_fault, probability);
+            }
+            _ => {
+                self.fault_probabilities.insert(fault, probability);
+            }
+        }
+    }
+
+    fn crash(&mut self) {
+        warn!("Simulating a crash, unsynced file contents are lost");
+        let metadata_loss = self.fault_probability(&FaultType::FileFaultType(
+            FileFaultType::FileMetadataSyncFailure,
+        ));
+        let rolled_back = self.fs.crash(&mut self.rng, metadata_loss);
+        for _ in 0..rolled_back {
+            let now = self.now();
+            self.faults_generated.push((
+                FaultType::FileFaultType(FileFaultType::FileMetadataSyncFailure),
+                now,
+            ));
+        }
+    }
+
+    fn file_snapshot(&self) -> Option<FileSnapshot> {
+        self.file
+            .as_ref()
+            .and_then(|log| log.segments().last())
+            .map(SimulatedFile::snapshot)
+    }
+
+    fn disk(&self) -> &SimulatedDisk {
+        self.fs.disk()
+    }
+
+    fn file_system(&self) -> &SimulatedFileSystem {
+        &self.fs
+    }
+
+    fn schedule_shutdown(&mut self, at: Duration) {
+        trace!("scheduling shutdown at {:?}", at);
+        self.shutdown_at = Some(at);
+    }
+
+    fn committed_offset(&self) -> Option<i64> {
+        self.committed_offset
+    }
+
+    fn segment_snapshots(&self) -> Vec<FileSnapshot> {
+        self.file
+            .as_ref()
+            .map(|log| log.segments().iter().map(SimulatedFile::snapshot).collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use tracing::{error, info, trace};
+
+use crate::{Errors, FaultType, MeteredIO, Metrics, SimulatedIO, SimulationControl, Workload, IO};
+
+/// When a run stops on its own. Unbounded by default.
+#[derive(Clone, Debug, Default)]
+pub struct RunLimits {
+    max_steps: Option<usize>,
+    max_virtual_time: Option<Duration>,
+}
+
+impl RunLimits {
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Stops once this much time has passed on the IO's clock since `init`.
+    pub fn with_max_virtual_time(mut self, max_virtual_time: Duration) -> Self {
+        self.max_virtual_time = Some(max_virtual_time);
+        self
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.max_steps.is_some() || self.max_virtual_time.is_some()
+    }
+
+    fn reached(&self, steps: usize, elapsed: Duration) -> bool {
+        self.max_steps.is_some_and(|max| steps >= max)
+            || self.max_virtual_time.is_some_and(|max| elapsed >= max)
+    }
+}
+
+/// What happened when a workload was driven against a single seed.
+#[derive(Debug)]
+pub struct SimulationOutcome {
+    pub seed: u64,
+    pub steps: usize,
+    /// Virtual time the run took.
+    pub elapsed: Duration,
+    /// How likely each fault was to be injected when the run started.
+    pub fault_profile: Vec<(FaultType, f64)>,
+    pub faults: Vec<InjectedFault>,
+    /// Where the run failed, if it did.
+    pub failed_phase: Option<Phase>,
+    pub error: Option<Errors>,
+    /// Everything the run did through its IO, faults included.
+    pub metrics: Metrics,
+}
+
+impl SimulationOutcome {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// `seed X: PASS after N steps, M faults`, with the error appended on failure.
+    pub fn summary(&self) -> String {
+        let verdict = format!(
+            "seed {}: {} after {} steps, {} faults",
+            self.seed,
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.steps,
+            self.faults.len()
+        );
+        match &self.error {
+            None => verdict,
+            Some(e) => format!("{}: {}", verdict, e),
+        }
+    }
+}
+
+/// The part of a run an error came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Init,
+    Step,
+    Check,
+    Shutdown,
+    Verify,
+}
+
+/// A fault the simulator injected, with the step it happened in (0 during `init`) and the
+/// virtual time it was injected at.
+#[derive(Clone, Debug)]
+pub struct InjectedFault {
+    pub fault: FaultType,
+    pub step: usize,
+    pub at: Duration,
+}
+
+/// Runs `init` followed by `step` + `check` until the workload fails, a limit is reached
+/// or the IO asks for a shutdown, in which case the workload is shut down cleanly. Without
+/// limits or a shutdown this only returns on failure.
+pub async fn run_workload<W: Workload + ?Sized>(
+    io: &mut dyn IO,
+    workload: &mut W,
+    limits: &RunLimits,
+) -> (usize, Result<(), Errors>) {
+    let (steps, result) = drive(io, workload, limits, |_, _| {}).await;
+    (steps, result.map_err(|(_, e)| e))
+}
+
+/// `run_workload`, calling `after_step` once `init` and then every step is done.
+async fn drive<W, F>(
+    io: &mut dyn IO,
+    workload: &mut W,
+    limits: &RunLimits,
+    mut after_step: F,
+) -> (usize, Result<(), (Phase, Errors)>)
+where
+    W: Workload + ?Sized,
+    F: FnMut(&mut dyn IO, usize),
+{
+    let started = io.now();
+    let result = workload.init(io).await;
+    after_step(io, 0);
+    if let Err(e) = result {
+        return (0, Err((Phase::Init, e)));
+    }
+    let mut steps = 0;
+    while !limits.reached(steps, io.now().saturating_sub(started)) {
+        if io.shutdown_requested() {
+            info!("shutting down after {} steps", steps);
+            let result = workload.shutdown(io).await;
+            after_step(io, steps);
+            return (steps, result.map_err(|e| (Phase::Shutdown, e)));
+        }
+        steps += 1;
+        trace!("running step {}", steps);
+        let result = match workload.step(io).await {
+            Ok(()) => workload.check(io).await.map_err(|e| (Phase::Check, e)),
+            Err(e) => Err((Phase::Step, e)),
+        };
+        after_step(io, steps);
+        if let Err(e) = result {
+            return (steps, Err(e));
+        }
+    }
+    (steps, Ok(()))
+}
+
+/// Drives a fresh workload against `SimulatedIO` seeded with `seed`. A run that survives
+/// its limits is shut down and has to pass a final `verify` before it counts as a pass.
+pub async fn simulate<W: Workload + ?Sized>(
+    seed: u64,
+    workload: &mut W,
+    limits: &RunLimits,
+) -> SimulationOutcome {
+    let metrics = Metrics::new();
+    let simulated = SimulatedIO::new(seed);
+    let fault_profile = FaultType::all()
+        .into_iter()
+        .map(|fault| {
+            let probability = simulated.fault_probability(&fault);
+            (fault, probability)
+        })
+        .collect();
+    let mut io = MeteredIO::new(simulated, metrics.clone());
+    let mut faults = Vec::new();
+    let mut collect_faults = |io: &mut dyn IO, step: usize| {
+        if let Some(sim) = io.simulation() {
+            faults.extend(
+                sim.take_timed_faults()
+                    .into_iter()
+                    .map(|(fault, at)| InjectedFault { fault, step, at }),
+            );
+        }
+    };
+    let (steps, mut result) = drive(&mut io, workload, limits, &mut collect_faults).await;
+    if result.is_ok() {
+        result = finish(&mut io, workload).await;
+        collect_faults(&mut io, steps);
+    }
+    metrics.record_faults(&faults.iter().map(|f| f.fault.clone()).collect::<Vec<_>>());
+    let (failed_phase, error) = match result {
+        Ok(()) => (None, None),
+        Err((phase, e)) => (Some(phase), Some(e)),
+    };
+    SimulationOutcome {
+        seed,
+        steps,
+        elapsed: io.now(),
+        fault_profile,
+        faults,
+        failed_phase,
+        error,
+        metrics,
+    }
+}
+
+async fn finish<W: Workload + ?Sized>(
+    io: &mut dyn IO,
+    workload: &mut W,
+) -> Result<(), (Phase, Errors)> {
+    //  A run that stopped by shutting down has nothing left to drain, so this is a no-op then
+    workload
+        .shutdown(io)
+        .await
+        .map_err(|e| (Phase::Shutdown, e))?;
+    workload.verify(io).await.map_err(|e| (Phase::Verify, e))
+}
+
+/// Runs one bounded simulation per seed, building a new workload for each so that
+/// no state leaks between seeds.
+pub async fn sweep_seeds<W, F>(
+    seeds: impl IntoIterator<Item = u64>,
+    limits: RunLimits,
+    make_workload: F,
+) -> Vec<SimulationOutcome>
+where
+    W: Workload,
+    F: Fn() -> W,
+{
+    let mut outcomes = Vec::new();
+    for seed in seeds {
+        let mut workload = make_workload();
+        let outcome = simulate(seed, &mut workload, &limits).await;
+        match outcome.passed() {
+            true => info!("{}", outcome.summary()),
+            false => error!("{}", outcome.summary()),
+        }
+        outcomes.push(outcome);
+    }
+    outcomes
+}
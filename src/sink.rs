@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, trace};
+
+use crate::{Errors, KafkaMessage, IO};
+
+/// One record of pipeline output, tagged with the Kafka position it was produced from so a
+/// record can be recognised when it is seen again.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputRecord {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub config: String,
+    pub message: String,
+}
+
+impl OutputRecord {
+    pub fn new(source: &KafkaMessage, config: String) -> Self {
+        Self {
+            topic: source.topic.clone(),
+            partition: source.partition,
+            offset: source.offset,
+            config,
+            message: source.payload.clone(),
+        }
+    }
+
+    /// A JSON object, so topics, config and messages can hold any text.
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("output records always serialize")
+    }
+
+    pub fn decode(payload: &str) -> Option<Self> {
+        serde_json::from_str(payload).ok()
+    }
+}
+
+/// When queued output records are written out and synced as one batch.
+#[derive(Clone, Debug)]
+pub struct BatchPolicy {
+    max_records: usize,
+    max_delay: Duration,
+}
+
+impl Default for BatchPolicy {
+    /// Batches of up to 16 records, held for at most 100ms.
+    fn default() -> Self {
+        Self {
+            max_records: 16,
+            max_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl BatchPolicy {
+    /// Flushes as soon as `count` records are queued. A count of 1 syncs every record.
+    pub fn with_max_records(mut self, count: usize) -> Self {
+        self.max_records = count.max(1);
+        self
+    }
+
+    /// Flushes once the oldest queued record has waited `delay`, measured with `IO::now`.
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    pub fn max_records(&self) -> usize {
+        self.max_records
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+}
+
+/// Writes output records at most once per Kafka offset.
+///
+/// Records for a partition reach the file in offset order, so remembering the highest offset
+/// written per partition is enough to recognise duplicates. After a restart the high
+/// watermarks are rebuilt from every record in the file.
+#[derive(Default)]
+pub struct IdempotentSink {
+    high_watermarks: HashMap<(String, i32), i64>,
+}
+
+impl IdempotentSink {
+    /// Rebuilds the high watermarks from the records already in the file. Every segment is
+    /// scanned, since a partition's last record can be anywhere in it.
+    pub async fn recover(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        let entries = io.read_last_n_entries(usize::MAX).await?;
+        for record in entries.iter().filter_map(|line| OutputRecord::decode(line)) {
+            self.mark_written(&record);
+        }
+        info!(
+            "recovered high watermarks from {} entries: {:?}",
+            entries.len(),
+            self.high_watermarks
+        );
+        Ok(())
+    }
+
+    pub fn is_written(&self, record: &OutputRecord) -> bool {
+        self.high_watermarks
+            .get(&(record.topic.clone(), record.partition))
+            .is_some_and(|&offset| record.offset <= offset)
+    }
+
+    /// Writes `record` unless it is already in the file. Returns whether anything was written.
+    pub async fn write(&mut self, io: &mut dyn IO, record: &OutputRecord) -> Result<bool, Errors> {
+        if self.is_written(record) {
+            trace!(
+                "skipping {}/{}@{}, already written",
+                record.topic,
+                record.partition,
+                record.offset
+            );
+            return Ok(false);
+        }
+        io.write_to_file(&record.encode()).await?;
+        self.mark_written(record);
+        Ok(true)
+    }
+
+    /// Writes the records of `records` that aren't in the file yet with a single write.
+    /// Returns the encoded records that were written.
+    pub async fn write_batch(
+        &mut self,
+        io: &mut dyn IO,
+        records: &[OutputRecord],
+    ) -> Result<Vec<String>, Errors> {
+        let (fresh, skipped): (Vec<&OutputRecord>, Vec<&OutputRecord>) =
+            records.iter().partition(|record| !self.is_written(record));
+        if !skipped.is_empty() {
+            trace!("skipping {} records already written", skipped.len());
+        }
+        if fresh.is_empty() {
+            return Ok(Vec::new());
+        }
+        let encoded: Vec<String> = fresh.iter().map(|record| record.encode()).collect();
+        io.write_batch_to_file(&encoded).await?;
+        for record in fresh {
+            self.mark_written(record);
+        }
+        Ok(encoded)
+    }
+
+    fn mark_written(&mut self, record: &OutputRecord) {
+        let offset = self
+            .high_watermarks
+            .entry((record.topic.clone(), record.partition))
+            .or_insert(record.offset);
+        *offset = (*offset).max(record.offset);
+    }
+}
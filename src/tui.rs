@@ -1,45 +1,116 @@
 use std::{
-    collections::VecDeque,
-    default, io,
-    time::{Duration, Instant, SystemTime},
+    collections::{BTreeMap, VecDeque},
+    io,
+    time::{Duration, Instant},
 };
 
 use color_eyre::Result;
 use rand::{seq::SliceRandom, RngCore};
+use ratatui::style::Modifier;
 use ratatui::{
     crossterm::event::{self, Event, KeyCode},
-    layout::{Alignment, Constraint, Layout},
+    layout::{Alignment, Constraint, Layout, Rect},
     style::{Color, Style},
-    text::Line,
+    text::{Line, Span},
     widgets::{Block, Borders, Paragraph},
     DefaultTerminal, Frame,
 };
-use ratatui::{prelude::Stylize, style::Modifier};
-use tracing::{error, info, trace};
+use tracing::{error, info, trace, warn};
 
+use crate::game::{levels, Level, Score};
 use crate::{
-    init_components, init_tracing, run_simulation_step, FaultType, FileFaultType, SimulatedIO,
+    frame, init_tracing, CircuitState, Errors, FaultType, FileFaultType, FileSnapshot, MeteredIO,
+    Metrics, SimulatedIO, SimulationControl, Workload, IO,
 };
 
-pub async fn run_tui() -> Result<()> {
+/// Runs the fault injection game against a workload built by `make_workload`.
+pub async fn run_tui<W, F>(make_workload: F) -> Result<()>
+where
+    W: Workload,
+    F: Fn() -> W,
+{
     color_eyre::install()?;
     init_tracing(crate::LogOptions::File);
     let mut terminal = ratatui::init();
-    let mut game_state = GameState::new();
-    let seed = match std::env::var("SEED") {
-        Ok(seed) => seed.parse::<u64>().unwrap(),
-        Err(_) => rand::thread_rng().next_u64(),
+    let mut app = App {
+        seed_input: std::env::var("SEED").unwrap_or_default(),
+        failed_seeds: load_failed_seeds(),
+        levels: levels(),
+        unlocked_levels: load_unlocked_levels(),
+        ..App::default()
     };
-    info!("Running game loop with seed {}", seed);
-    let mut io = SimulatedIO::new(seed);
-    let config_key = "config_key";
-    let app_result = App::default()
-        .run(&mut terminal, &mut io, &config_key, seed)
-        .await;
+    app.level = app.unlocked_levels - 1;
+    if app.seed_input.is_empty() {
+        app.seed_selection = app.seed_options().len() - 1;
+    }
+    let app_result = app.run(&mut terminal, &make_workload).await;
     ratatui::restore();
     Ok(app_result?)
 }
 
+/// Where the seeds of failed games are kept between sessions, most recent first.
+const FAILED_SEEDS_FILE: &str = ".dst-failed-seeds";
+
+/// How many failed seeds the start screen offers to replay.
+const MAX_FAILED_SEEDS: usize = 10;
+
+fn load_failed_seeds() -> Vec<u64> {
+    std::fs::read_to_string(FAILED_SEEDS_FILE)
+        .map(|contents| {
+            contents
+                .lines()
+                .filter_map(|line| line.trim().parse().ok())
+                .take(MAX_FAILED_SEEDS)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn save_failed_seeds(seeds: &[u64]) {
+    let contents: String = seeds.iter().map(|seed| format!("{}\n", seed)).collect();
+    if let Err(e) = std::fs::write(FAILED_SEEDS_FILE, contents) {
+        warn!(
+            "failed to save failed seeds to {}: {}",
+            FAILED_SEEDS_FILE, e
+        );
+    }
+}
+
+/// Simulated IO, metered so the topology view can show error rates and retries.
+type GameIO = MeteredIO<SimulatedIO>;
+
+fn game_io(seed: u64) -> GameIO {
+    MeteredIO::new(SimulatedIO::new(seed), Metrics::new())
+}
+
+/// Where the number of unlocked levels is kept between sessions.
+const PROGRESS_FILE: &str = ".dst-progress";
+
+fn load_unlocked_levels() -> usize {
+    std::fs::read_to_string(PROGRESS_FILE)
+        .ok()
+        .and_then(|contents| contents.trim().parse().ok())
+        .unwrap_or(1)
+        .clamp(1, levels().len())
+}
+
+fn save_unlocked_levels(unlocked: usize) {
+    if let Err(e) = std::fs::write(PROGRESS_FILE, format!("{}\n", unlocked)) {
+        warn!("failed to save progress to {}: {}", PROGRESS_FILE, e);
+    }
+}
+
+/// Records in `file` that were written but not synced, which a crash would lose.
+fn unsynced_records(file: &FileSnapshot) -> usize {
+    frame::scan(&file.contents)
+        .records
+        .iter()
+        .filter(|(offset, payload)| {
+            offset + frame::framed_len(payload.len()) > file.synced_contents.len()
+        })
+        .count()
+}
+
 impl FaultType {
     fn to_symbol(&self) -> &str {
         match self {
@@ -69,105 +140,772 @@ impl FaultType {
     }
 }
 
-struct GameState {
-    active_faults: VecDeque<(FaultType, u8)>,
-    fault_log: VecDeque<String>,
-    tick_count: u64,
+#[derive(Default, PartialEq)]
+enum AppState {
+    #[default]
+    StartScreen,
+    Running,
+    GameOver,
+    LevelComplete,
+}
+
+/// How many of the last records in the output file each step keeps for inspection.
+const FILE_TAIL_RECORDS: usize = 5;
+
+/// How many of the last bytes of the output file the file inspector dumps.
+const FILE_TAIL_BYTES: usize = 96;
+
+/// What a crash would do to a record.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Durability {
+    Synced,
+    /// Straddles the synced length, so a crash would leave it torn.
+    Torn,
+    Unsynced,
+}
+
+impl Durability {
+    fn color(self) -> Color {
+        match self {
+            Durability::Synced => Color::Green,
+            Durability::Torn => Color::Magenta,
+            Durability::Unsynced => Color::Yellow,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct RecordView {
+    offset: usize,
+    framed_len: usize,
+    payload: String,
+    durability: Durability,
+}
+
+/// The output segment as one step left it: what was written, what was synced and what a
+/// crash would lose.
+#[derive(Clone, Debug, Default)]
+struct FileView {
+    len: usize,
+    synced_len: usize,
+    read_position: usize,
+    /// Length of the prefix made up of valid frames. Anything after it is torn or corrupt.
+    valid_len: usize,
+    /// The last few records, oldest first.
+    tail: Vec<RecordView>,
+    /// Records a crash would lose entirely, and records it would leave torn.
+    lost_records: usize,
+    torn_records: usize,
+    /// The last `FILE_TAIL_BYTES` bytes of the file, starting at `tail_offset`.
+    tail_offset: usize,
+    tail_bytes: Vec<u8>,
 }
 
-impl GameState {
-    fn new() -> Self {
+impl FileView {
+    fn new(file: &FileSnapshot) -> Self {
+        let scan = frame::scan(&file.contents);
+        let synced_len = file.synced_contents.len().min(file.contents.len());
+        let durability = |offset: usize, framed_len: usize| {
+            if offset + framed_len <= synced_len {
+                Durability::Synced
+            } else if offset < synced_len {
+                Durability::Torn
+            } else {
+                Durability::Unsynced
+            }
+        };
+        let records: Vec<_> = scan
+            .records
+            .iter()
+            .map(|(offset, payload)| RecordView {
+                offset: *offset,
+                framed_len: frame::framed_len(payload.len()),
+                payload: String::from_utf8_lossy(payload).into_owned(),
+                durability: durability(*offset, frame::framed_len(payload.len())),
+            })
+            .collect();
+        let count = |wanted: Durability| records.iter().filter(|r| r.durability == wanted).count();
+        //  Start the dump on a row boundary so offsets line up
+        let tail_offset = file.contents.len().saturating_sub(FILE_TAIL_BYTES) / 16 * 16;
         Self {
-            active_faults: VecDeque::new(),
-            fault_log: VecDeque::new(),
-            tick_count: 0,
+            len: file.contents.len(),
+            synced_len,
+            read_position: file.read_position,
+            valid_len: scan.valid_len,
+            lost_records: count(Durability::Unsynced),
+            torn_records: count(Durability::Torn),
+            tail: records[records.len().saturating_sub(FILE_TAIL_RECORDS)..].to_vec(),
+            tail_offset,
+            tail_bytes: file.contents[tail_offset..].to_vec(),
         }
     }
 
-    fn add_fault(&mut self, fault: FaultType) {
-        self.active_faults.push_back((fault.clone(), 0));
-        self.fault_log.push_back(fault.to_log_message());
-        if self.fault_log.len() > 20 {
-            self.fault_log.pop_front();
+    fn unsynced_bytes(&self) -> usize {
+        self.len - self.synced_len
+    }
+
+    fn byte_color(&self, offset: usize) -> Color {
+        if offset >= self.valid_len {
+            Color::Red
+        } else if offset >= self.synced_len {
+            Color::Yellow
+        } else {
+            Color::Green
         }
     }
+}
 
-    fn tick(&mut self) {
-        self.tick_count = self.tick_count.wrapping_add(1);
-        for (_, pos) in self.active_faults.iter_mut() {
-            *pos = pos.saturating_add(1);
+/// What one step of the run did, kept so the game can be scrubbed back to it. Step 0 is
+/// `init`.
+#[derive(Clone, Debug, Default)]
+struct StepRecord {
+    step: usize,
+    virtual_time: Duration,
+    faults: Vec<FaultType>,
+    /// Status lines logged during the step, numbered across the whole run.
+    status: Vec<String>,
+    circuit_states: Vec<(&'static str, CircuitState)>,
+    /// The output segment being written.
+    file: FileView,
+    file_open: bool,
+    /// Calls and failed calls of every IO operation so far.
+    operations: BTreeMap<&'static str, (u64, u64)>,
+    /// Retries of every IO operation so far.
+    retries: BTreeMap<String, u64>,
+    error: Option<String>,
+}
+
+impl StepRecord {
+    /// Whether a replay of the seed reproduced this step.
+    fn matches(&self, other: &StepRecord) -> bool {
+        self.virtual_time == other.virtual_time
+            && self.faults == other.faults
+            && self.status == other.status
+            && self.error == other.error
+    }
+}
+
+/// How many steps back the topology view looks for errors and retries.
+const HEALTH_WINDOW: usize = 20;
+
+/// A node of the pipeline in the topology view, in the order data flows through them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Component {
+    Kafka,
+    Processor,
+    Redis,
+    File,
+}
+
+impl Component {
+    const ALL: [Component; 4] = [
+        Component::Kafka,
+        Component::Processor,
+        Component::Redis,
+        Component::File,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Component::Kafka => "Kafka",
+            Component::Processor => "Processor",
+            Component::Redis => "Redis",
+            Component::File => "File",
         }
-        while self
-            .active_faults
-            .front()
-            .map_or(false, |(_, pos)| *pos >= 10)
-        {
-            self.active_faults.pop_front();
+    }
+
+    /// The component an IO operation talks to. Anything not Kafka or Redis is the filesystem.
+    fn of_operation(operation: &str) -> Component {
+        if operation.contains("kafka") {
+            Component::Kafka
+        } else if operation.contains("redis") {
+            Component::Redis
+        } else if matches!(operation, "shutdown" | "verify" | "unknown") {
+            Component::Processor
+        } else {
+            Component::File
         }
     }
 }
 
-#[derive(Default, PartialEq)]
-enum AppState {
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Health {
+    Healthy,
+    /// Up, but failing or being retried lately.
+    Degraded,
+    Down,
+}
+
+impl Health {
+    fn color(self) -> Color {
+        match self {
+            Health::Healthy => Color::Green,
+            Health::Degraded => Color::Yellow,
+            Health::Down => Color::Red,
+        }
+    }
+}
+
+/// How a component fared over the last `HEALTH_WINDOW` steps.
+#[derive(Clone, Debug)]
+struct ComponentHealth {
+    component: Component,
+    health: Health,
+    /// Connection state, or what the component is doing.
+    state: String,
+    calls: u64,
+    errors: u64,
+    retries: u64,
+}
+
+/// How fast the live run advances.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Speed {
     #[default]
-    StartScreen,
-    Running,
-    GameOver,
+    Normal,
+    Fast,
+    Max,
+}
+
+impl Speed {
+    /// Wall-clock time between steps, or `None` to run as many steps as fit in a frame.
+    fn step_interval(self) -> Option<Duration> {
+        match self {
+            Speed::Normal => Some(Duration::from_millis(100)),
+            Speed::Fast => Some(Duration::from_millis(10)),
+            Speed::Max => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Speed::Normal => "1x",
+            Speed::Fast => "10x",
+            Speed::Max => "max",
+        }
+    }
+}
+
+/// How much the tuning panel's `+` and `-` keys change a fault probability by.
+const PROBABILITY_STEP: f64 = 0.05;
+
+/// The faults the tuning panel offers: every fault that can actually fire during a game.
+/// Nothing injects `FileOpenFailure`, `FileReadFailure` only fires on raw reads the pipeline
+/// never makes, and `FileMetadataSyncFailure` only matters when the workload crashes, which
+/// the game never does.
+fn tunable_faults() -> Vec<FaultType> {
+    FaultType::all()
+        .into_iter()
+        .filter(|fault| {
+            !matches!(
+                fault,
+                FaultType::FileOpenFailure
+                    | FaultType::FileFaultType(
+                        FileFaultType::FileReadFailure | FileFaultType::FileMetadataSyncFailure
+                    )
+            )
+        })
+        .collect()
+}
+
+/// A fault probability changed from the tuning panel, applied just before `step` ran.
+/// Together with the seed, these reproduce a tuned run.
+#[derive(Clone, Debug)]
+struct ProbabilityChange {
+    step: usize,
+    fault: FaultType,
+    probability: f64,
+}
+
+/// How long a frame may spend stepping at `Speed::Max` before it has to redraw.
+const MAX_SPEED_FRAME_BUDGET: Duration = Duration::from_millis(40);
+
+/// How long a finished run stays on screen before the game over or level complete screen
+/// replaces it.
+const END_SCREEN_DELAY: Duration = Duration::from_secs(2);
+
+/// A seed the start screen offers to run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SeedOption {
+    Typed,
+    Failed(u64),
+    Random,
 }
 
 #[derive(Default)]
 struct App {
     state: AppState,
+    seed: u64,
+    /// Digits typed on the start screen.
+    seed_input: String,
+    /// Index into `seed_options` of the start screen's selection.
+    seed_selection: usize,
+    failed_seeds: Vec<u64>,
     active_faults: VecDeque<(FaultType, u8)>,
-    fault_log: VecDeque<String>,
-    status_log: VecDeque<String>,
     status_log_counter: usize,
     tick_count: u64,
     death_reason: Option<String>,
+    history: Vec<StepRecord>,
+    /// The step being inspected, or `None` while following the live run.
+    cursor: Option<usize>,
+    /// Whether the run has failed, after which it can only be inspected.
+    finished: bool,
+    paused: bool,
+    speed: Speed,
+    /// When the finished run gives way to the game over or level complete screen.
+    end_screen_at: Option<Instant>,
+    /// Whether the fault probability panel replaces the fault log.
+    tuning: bool,
+    /// Whether the file inspector replaces the fault log.
+    inspecting_file: bool,
+    /// Index into `tunable_faults()` of the fault being tuned.
+    tuning_selection: usize,
+    probability_changes: Vec<ProbabilityChange>,
+    levels: Vec<Level>,
+    /// Index into `levels` of the level being played.
+    level: usize,
+    /// How many levels, from the first, can be picked on the start screen.
+    unlocked_levels: usize,
+    level_passed: bool,
+    /// Records a crash would have lost when the run ended.
+    records_lost: usize,
 }
 
 impl App {
     fn add_fault(&mut self, fault: FaultType) {
-        self.active_faults.push_back((fault.clone(), 0));
-        self.fault_log.push_back(fault.to_log_message());
-        if self.fault_log.len() > 20 {
-            self.fault_log.pop_front();
+        self.active_faults.push_back((fault, 0));
+    }
+
+    /// Numbers `messages` for the status log.
+    fn number_status_messages(&mut self, messages: Vec<String>) -> Vec<String> {
+        messages
+            .into_iter()
+            .map(|msg| {
+                let line = format!("[{}] {}", self.status_log_counter, msg);
+                self.status_log_counter += 1;
+                line
+            })
+            .collect()
+    }
+
+    /// Describes every circuit breaker transition since the previous step.
+    fn circuit_transitions(&self, states: &[(&'static str, CircuitState)]) -> Vec<String> {
+        let previous_states = self
+            .history
+            .last()
+            .map_or(&[][..], |record| &record.circuit_states);
+        let mut transitions = vec![];
+        for (name, state) in states {
+            let previous = previous_states
+                .iter()
+                .find(|(other, _)| other == name)
+                .map_or(CircuitState::Closed, |(_, previous)| *previous);
+            if previous != *state {
+                transitions.push(format!("{} circuit {} -> {}", name, previous, state));
+            }
         }
+        transitions
     }
 
-    fn add_connection_status_messages(&mut self) {
-        let messages = [
-            format!("[{}] Connected to Kafka", self.status_log_counter),
-            format!("[{}] Connected to Redis", self.status_log_counter + 1),
-            format!("[{}] Opened file descriptor", self.status_log_counter + 2),
-        ];
+    /// Records the step that just ran against `io`, along with the error it failed with.
+    fn record_step<W: Workload>(&mut self, io: &mut GameIO, workload: &W, error: Option<&Errors>) {
+        let circuit_states: Vec<_> = workload
+            .circuit_breakers()
+            .iter()
+            .map(|breaker| (breaker.name(), breaker.state()))
+            .collect();
+        let mut messages = workload.status_messages();
+        messages.extend(self.circuit_transitions(&circuit_states));
+        let status = self.number_status_messages(messages);
+        let snapshot = io.inner().file_snapshot();
+        let file_open = snapshot.is_some();
+        let file = FileView::new(&snapshot.unwrap_or_default());
+        let faults = io.inner_mut().get_generated_faults();
+        info!("the generated faults {:?}", faults);
+        self.history.push(StepRecord {
+            step: self.history.len(),
+            virtual_time: io.now(),
+            faults,
+            status,
+            circuit_states,
+            file,
+            file_open,
+            operations: io.metrics().map(Metrics::operations).unwrap_or_default(),
+            retries: io.metrics().map(Metrics::retries).unwrap_or_default(),
+            error: error.map(Errors::to_string),
+        });
+    }
+
+    /// Moves the probability of the selected fault by `delta` from the next step on.
+    fn adjust_probability(&mut self, io: &mut GameIO, delta: f64) {
+        let fault = tunable_faults().swap_remove(self.tuning_selection);
+        //  Rounded so repeated steps don't drift away from round percentages
+        let probability = ((io.inner().fault_probability(&fault) + delta) * 100.0).round() / 100.0;
+        let probability = probability.clamp(0.0, 1.0);
+        let step = self.history.len();
+        info!(
+            "set probability of {:?} to {} before step {}",
+            fault, probability, step
+        );
+        io.inner_mut()
+            .set_fault_probability(fault.clone(), probability);
+        self.probability_changes.push(ProbabilityChange {
+            step,
+            fault,
+            probability,
+        });
+    }
+
+    fn select_fault(&mut self, offset: isize) {
+        let faults = tunable_faults().len();
+        self.tuning_selection = self
+            .tuning_selection
+            .saturating_add_signed(offset)
+            .min(faults - 1);
+    }
+
+    /// Runs `init` as the first step and `step` + `check` after that, recording the result.
+    async fn advance<W: Workload>(
+        &mut self,
+        io: &mut GameIO,
+        workload: &mut W,
+    ) -> Result<(), Errors> {
+        //  Live changes were applied already; this is for replays
+        let step = self.history.len();
+        for change in self.probability_changes.iter().filter(|c| c.step == step) {
+            io.inner_mut()
+                .set_fault_probability(change.fault.clone(), change.probability);
+        }
+        let result = if self.history.is_empty() {
+            workload.init(io).await
+        } else {
+            match workload.step(io).await {
+                Ok(()) => workload.check(io).await,
+                Err(e) => Err(e),
+            }
+        };
+        self.record_step(io, workload, result.as_ref().err());
+        result
+    }
 
-        for message in messages {
-            self.status_log.push_back(message);
+    /// Once the workload has survived the level, shuts it down and verifies that nothing
+    /// was lost.
+    async fn finish_level<W: Workload>(
+        &mut self,
+        io: &mut GameIO,
+        workload: &mut W,
+    ) -> Result<(), Errors> {
+        info!(
+            "survived level {}, shutting down to verify",
+            self.levels[self.level].name
+        );
+        workload.shutdown(io).await?;
+        workload.verify(io).await?;
+        self.level_passed = true;
+        self.finished = true;
+        self.end_screen_at = Some(Instant::now() + END_SCREEN_DELAY);
+        //  Turning faults down from the tuning panel is practice, it doesn't unlock anything
+        if !self.tuned() && self.unlocked_levels < self.levels.len().min(self.level + 2) {
+            self.unlocked_levels = self.level + 2;
+            save_unlocked_levels(self.unlocked_levels);
         }
+        Ok(())
+    }
+
+    /// Whether any fault probability was changed away from the level's.
+    fn tuned(&self) -> bool {
+        !self.probability_changes.is_empty()
+    }
 
-        while self.status_log.len() > 50 {
-            self.status_log.pop_front();
+    /// Steps survived and faults absorbed so far, less what a crash would have lost.
+    fn score(&self) -> Score {
+        let survived = self.history.iter().filter(|record| record.error.is_none());
+        Score {
+            steps: survived.clone().filter(|record| record.step > 0).count(),
+            faults_absorbed: survived.map(|record| record.faults.len()).sum(),
+            records_lost: self.records_lost,
         }
-        self.status_log_counter += 3;
     }
 
-    fn add_status_messages(&mut self) {
-        let messages = [
-            format!("[{}] Read messages from Kafka", self.status_log_counter),
-            format!("[{}] Read messages from Redis", self.status_log_counter + 1),
-            format!("[{}] Wrote output to file", self.status_log_counter + 2),
-        ];
+    async fn run_live_step<W: Workload>(&mut self, io: &mut GameIO, workload: &mut W) {
+        let mut result = self.advance(io, workload).await;
+        if result.is_ok() && self.history.len() > self.levels[self.level].steps_to_pass {
+            result = self.finish_level(io, workload).await;
+        }
+        trace!("ran single step of the simulation");
+        let faults = self
+            .history
+            .last()
+            .map(|record| record.faults.clone())
+            .unwrap_or_default();
+        //  Faults fly in step by step, so the animation keeps pace with the run at any speed
+        self.tick();
+        for fault in faults {
+            self.add_fault(fault);
+        }
+        if let Err(e) = result {
+            //  TODO: Found an error. What should I do? Log it?
+            error!("error while running simulation step {}", e);
+            self.death_reason = Some(e.to_string());
+            self.finished = true;
+            self.records_lost = io
+                .inner()
+                .file_snapshot()
+                .as_ref()
+                .map_or(0, unsynced_records);
+            self.end_screen_at = Some(Instant::now() + END_SCREEN_DELAY);
+            self.remember_failed_seed();
+        }
+    }
+
+    fn remember_failed_seed(&mut self) {
+        let seed = self.seed;
+        self.failed_seeds.retain(|&failed| failed != seed);
+        self.failed_seeds.insert(0, seed);
+        self.failed_seeds.truncate(MAX_FAILED_SEEDS);
+        save_failed_seeds(&self.failed_seeds);
+    }
+
+    /// The typed seed, then the failed seeds, then a random one.
+    fn seed_options(&self) -> Vec<SeedOption> {
+        let mut options = vec![SeedOption::Typed];
+        options.extend(
+            self.failed_seeds
+                .iter()
+                .map(|&seed| SeedOption::Failed(seed)),
+        );
+        options.push(SeedOption::Random);
+        options
+    }
+
+    /// The seed the start screen's selection stands for, if the typed one parses.
+    fn chosen_seed(&self) -> Option<u64> {
+        match self.seed_options()[self.seed_selection] {
+            SeedOption::Typed => self.seed_input.parse().ok(),
+            SeedOption::Failed(seed) => Some(seed),
+            SeedOption::Random => Some(rand::thread_rng().next_u64()),
+        }
+    }
+
+    /// Runs the steps due since the last frame at the current speed.
+    async fn run_due_steps<W: Workload>(
+        &mut self,
+        io: &mut GameIO,
+        workload: &mut W,
+        next_step: &mut Instant,
+    ) {
+        let frame_started = Instant::now();
+        match self.speed.step_interval() {
+            Some(interval) => {
+                //  Don't race through the steps missed while paused or inspecting
+                if frame_started.saturating_duration_since(*next_step) > interval {
+                    *next_step = frame_started;
+                }
+                while *next_step <= Instant::now() && !self.finished {
+                    self.run_live_step(io, workload).await;
+                    *next_step += interval;
+                }
+            }
+            None => {
+                while frame_started.elapsed() < MAX_SPEED_FRAME_BUDGET && !self.finished {
+                    self.run_live_step(io, workload).await;
+                }
+            }
+        }
+    }
+
+    /// Puts `seed` back at step 0 with a fresh IO and workload, keeping the probability
+    /// changes so they are replayed.
+    fn restart<W, F>(&mut self, io: &mut GameIO, workload: &mut W, make_workload: &F)
+    where
+        W: Workload,
+        F: Fn() -> W,
+    {
+        info!("Running game loop with seed {}", self.seed);
+        *io = game_io(self.seed);
+        let level = &self.levels[self.level];
+        for fault in FaultType::all() {
+            io.inner_mut()
+                .set_fault_probability(fault.clone(), level.fault_probability(&fault));
+        }
+        *workload = make_workload();
+        self.history.clear();
+        self.status_log_counter = 0;
+        self.active_faults.clear();
+        self.tick_count = 0;
+        self.death_reason = None;
+        self.finished = false;
+        self.end_screen_at = None;
+        self.level_passed = false;
+        self.records_lost = 0;
+        self.cursor = None;
+    }
+
+    /// Rewinds the run to `target` by replaying the seed from scratch against a fresh
+    /// workload. Everything recorded after `target` is dropped and the run goes live again.
+    async fn rewind<W, F>(
+        &mut self,
+        io: &mut GameIO,
+        workload: &mut W,
+        make_workload: &F,
+        target: usize,
+    ) where
+        W: Workload,
+        F: Fn() -> W,
+    {
+        let seed = self.seed;
+        info!("rewinding to step {} by replaying seed {}", target, seed);
+        let recorded = std::mem::take(&mut self.history);
+        //  Changes made after `target` belong to the future being discarded
+        self.probability_changes
+            .retain(|change| change.step <= target);
+        self.restart(io, workload, make_workload);
+        let mut diverged_at = None;
+        while self.history.len() <= target {
+            let result = self.advance(io, workload).await;
+            let step = self.history.len() - 1;
+            let reproduced = recorded
+                .get(step)
+                .is_some_and(|record| record.matches(&self.history[step]));
+            if !reproduced && diverged_at.is_none() {
+                warn!("replay of seed {} diverged at step {}", seed, step);
+                diverged_at = Some(step);
+            }
+            if let Err(e) = result {
+                self.death_reason = Some(e.to_string());
+                self.finished = true;
+                break;
+            }
+        }
+        if let (Some(step), Some(last)) = (diverged_at, self.history.last_mut()) {
+            last.status.push(format!(
+                "Replay diverged from the recorded run at step {}",
+                step
+            ));
+        }
+    }
 
-        for msg in messages {
-            self.status_log.push_back(msg);
+    /// The step on screen: the one being inspected, otherwise the latest.
+    fn selected(&self) -> Option<usize> {
+        self.cursor.or(self.history.len().checked_sub(1))
+    }
+
+    fn selected_record(&self) -> Option<&StepRecord> {
+        self.selected().and_then(|step| self.history.get(step))
+    }
+
+    /// Records up to and including the selected step.
+    fn visible_history(&self) -> &[StepRecord] {
+        let end = self.selected().map_or(0, |step| step + 1);
+        &self.history[..end]
+    }
+
+    /// Health of every component as of the selected step.
+    fn component_health(&self) -> Vec<ComponentHealth> {
+        let Some(record) = self.selected_record() else {
+            return Vec::new();
+        };
+        let before = record
+            .step
+            .checked_sub(HEALTH_WINDOW)
+            .map(|step| &self.history[step]);
+        Component::ALL
+            .iter()
+            .map(|&component| {
+                let mut calls = 0;
+                let mut errors = 0;
+                for (operation, (count, failed)) in &record.operations {
+                    if Component::of_operation(operation) == component {
+                        let (count_before, failed_before) = before
+                            .and_then(|before| before.operations.get(operation))
+                            .copied()
+                            .unwrap_or_default();
+                        calls += count - count_before;
+                        errors += failed - failed_before;
+                    }
+                }
+                let retries = record
+                    .retries
+                    .iter()
+                    .filter(|(operation, _)| Component::of_operation(operation) == component)
+                    .map(|(operation, count)| {
+                        count
+                            - before
+                                .and_then(|before| before.retries.get(operation))
+                                .copied()
+                                .unwrap_or_default()
+                    })
+                    .sum();
+                let circuit = record
+                    .circuit_states
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(component.name()))
+                    .map(|(_, state)| *state);
+                let (down, state) = match component {
+                    Component::Processor => match &record.error {
+                        Some(_) => (true, format!("failed at step {}", record.step)),
+                        None => (false, format!("step {}", record.step)),
+                    },
+                    Component::File => match record.file_open {
+                        true => (
+                            false,
+                            format!("open, {} B unsynced", record.file.unsynced_bytes()),
+                        ),
+                        false => (true, "not open".to_string()),
+                    },
+                    Component::Kafka | Component::Redis => match circuit {
+                        Some(state) => (state == CircuitState::Open, format!("circuit {}", state)),
+                        None => (false, "no circuit".to_string()),
+                    },
+                };
+                let health = if down {
+                    Health::Down
+                } else if errors > 0
+                    || retries > 0
+                    || circuit.is_some_and(|state| state != CircuitState::Closed)
+                {
+                    Health::Degraded
+                } else {
+                    Health::Healthy
+                };
+                ComponentHealth {
+                    component,
+                    health,
+                    state,
+                    calls,
+                    errors,
+                    retries,
+                }
+            })
+            .collect()
+    }
+
+    fn scrub_back(&mut self) {
+        if let Some(step) = self.selected() {
+            self.cursor = Some(step.saturating_sub(1));
+        }
+    }
+
+    /// Moves one step forward, back to the live run once past the latest step.
+    fn scrub_forward(&mut self) {
+        if let Some(step) = self.cursor {
+            self.cursor = Some(step + 1).filter(|&next| next + 1 < self.history.len());
         }
+    }
 
-        while self.status_log.len() > 50 {
-            // Keep more messages for scrolling effect
-            self.status_log.pop_front();
+    /// Moves to the nearest step before or after the selected one that injected a fault.
+    fn jump_to_fault(&mut self, forward: bool) {
+        let Some(selected) = self.selected() else {
+            return;
+        };
+        let has_faults = |step: &usize| !self.history[*step].faults.is_empty();
+        let found = match forward {
+            true => (selected + 1..self.history.len()).find(has_faults),
+            false => (0..selected).rev().find(has_faults),
+        };
+        if let Some(step) = found {
+            self.cursor = Some(step);
         }
-        self.status_log_counter += 3;
     }
 
     fn tick(&mut self) {
@@ -178,7 +916,7 @@ impl App {
         while self
             .active_faults
             .front()
-            .map_or(false, |(_, pos)| *pos >= 10)
+            .is_some_and(|(_, pos)| *pos >= 10)
         {
             let entry = self.active_faults.pop_front();
             if let Some(e) = entry {
@@ -187,19 +925,20 @@ impl App {
         }
     }
 
-    pub async fn run(
+    pub async fn run<W, F>(
         &mut self,
         terminal: &mut DefaultTerminal,
-        io: &mut SimulatedIO,
-        config_key: &str,
-        seed: u64,
-    ) -> io::Result<()> {
-        let mut last_tick = Instant::now();
-        let tick_rate = Duration::from_secs(1);
-        let mut written_messages = Vec::new();
-        let mut failed_writes = Vec::new();
-        let mut counter = 0;
-        let mut has_initialised = false;
+        make_workload: &F,
+    ) -> io::Result<()>
+    where
+        W: Workload,
+        F: Fn() -> W,
+    {
+        //  Replaced by a fresh pair once a seed is picked
+        let mut io = game_io(self.seed);
+        let mut workload = make_workload();
+        let (io, workload) = (&mut io, &mut workload);
+        let mut next_step = Instant::now();
 
         loop {
             if event::poll(Duration::from_millis(50))? {
@@ -207,99 +946,134 @@ impl App {
                     match self.state {
                         AppState::StartScreen => match key.code {
                             KeyCode::Enter => {
-                                self.state = AppState::Running;
+                                if let Some(seed) = self.chosen_seed() {
+                                    self.seed = seed;
+                                    self.probability_changes.clear();
+                                    self.restart(io, workload, make_workload);
+                                    self.state = AppState::Running;
+                                }
                             }
-                            KeyCode::Char(q) => {
-                                break;
+                            KeyCode::Char(digit) if digit.is_ascii_digit() => {
+                                self.seed_input.push(digit);
+                                self.seed_selection = 0;
                             }
+                            KeyCode::Backspace => {
+                                self.seed_input.pop();
+                                self.seed_selection = 0;
+                            }
+                            KeyCode::Up => {
+                                self.seed_selection = self.seed_selection.saturating_sub(1)
+                            }
+                            KeyCode::Left => self.level = self.level.saturating_sub(1),
+                            KeyCode::Right => {
+                                self.level = (self.level + 1).min(self.unlocked_levels - 1)
+                            }
+                            KeyCode::Down => {
+                                self.seed_selection =
+                                    (self.seed_selection + 1).min(self.seed_options().len() - 1)
+                            }
+                            KeyCode::Char('q') | KeyCode::Esc => break,
                             _ => (),
                         },
-                        AppState::Running => {
-                            if key.code == KeyCode::Char('q') {
-                                break;
+                        AppState::Running => match key.code {
+                            KeyCode::Char('q') => break,
+                            KeyCode::Char('p') | KeyCode::Char(' ') => self.paused = !self.paused,
+                            KeyCode::Char('s') if self.cursor.is_none() && !self.finished => {
+                                self.paused = true;
+                                self.run_live_step(io, workload).await;
                             }
-                        }
-                        AppState::GameOver => {
-                            if key.code == KeyCode::Enter {
-                                break;
+                            KeyCode::Char('t') => {
+                                self.tuning = !self.tuning;
+                                self.inspecting_file = false;
                             }
-                        }
-                    }
-                }
-            }
-
-            if self.state == AppState::Running {
-                if !has_initialised {
-                    match init_components(io).await {
-                        Ok(faults) => {
-                            for fault in faults {
-                                self.add_fault(fault);
+                            KeyCode::Char('f') => {
+                                self.inspecting_file = !self.inspecting_file;
+                                self.tuning = false;
                             }
-                            has_initialised = true;
-                            self.add_connection_status_messages();
-                        }
-                        Err(e) => {
-                            //  TODO: Found an error. What should I do? Log it?
-                            error!("error while initialising components for simulation {:?}", e);
-                            self.death_reason = Some(format!("{:?}", e));
-                            self.state = AppState::GameOver;
-                            std::thread::sleep(Duration::from_secs(2));
-                        }
-                    }
-                }
-                info!("Done initialising the components while running game loop");
-
-                match run_simulation_step(
-                    io,
-                    config_key,
-                    &mut counter,
-                    &mut written_messages,
-                    &mut failed_writes,
-                )
-                .await
-                {
-                    Ok(faults) => {
-                        info!("the generated faults {:?}", faults);
-                        for fault in faults {
-                            self.add_fault(fault);
-                        }
-                        self.add_status_messages();
-                    }
-                    Err(e) => {
-                        //  TODO: Found an error. What should I do? Log it?
-                        error!("error while running run_simulation_step {:?}", e);
-                        self.death_reason = Some(format!("{:?}", e));
-                        self.state = AppState::GameOver;
-                        std::thread::sleep(Duration::from_secs(2));
+                            KeyCode::Up if self.tuning => self.select_fault(-1),
+                            KeyCode::Down if self.tuning => self.select_fault(1),
+                            KeyCode::Char('+') | KeyCode::Char('=')
+                                if self.tuning && self.cursor.is_none() =>
+                            {
+                                self.adjust_probability(io, PROBABILITY_STEP)
+                            }
+                            KeyCode::Char('-') if self.tuning && self.cursor.is_none() => {
+                                self.adjust_probability(io, -PROBABILITY_STEP)
+                            }
+                            KeyCode::Char('1') => self.speed = Speed::Normal,
+                            KeyCode::Char('2') => self.speed = Speed::Fast,
+                            KeyCode::Char('3') => self.speed = Speed::Max,
+                            KeyCode::Left => self.scrub_back(),
+                            KeyCode::Right => self.scrub_forward(),
+                            KeyCode::Char('[') => self.jump_to_fault(false),
+                            KeyCode::Char(']') => self.jump_to_fault(true),
+                            KeyCode::End => self.cursor = None,
+                            KeyCode::Enter => {
+                                if let Some(target) = self.cursor {
+                                    self.rewind(io, workload, make_workload, target).await;
+                                }
+                            }
+                            _ => (),
+                        },
+                        AppState::GameOver | AppState::LevelComplete => match key.code {
+                            KeyCode::Enter => break,
+                            KeyCode::Char('r') => {
+                                self.restart(io, workload, make_workload);
+                                self.state = AppState::Running;
+                            }
+                            KeyCode::Char('n')
+                                if self.level_passed && self.level + 1 < self.unlocked_levels =>
+                            {
+                                self.level += 1;
+                                self.probability_changes.clear();
+                                self.restart(io, workload, make_workload);
+                                self.state = AppState::Running;
+                            }
+                            KeyCode::Left => {
+                                self.state = AppState::Running;
+                                self.cursor = self.history.len().checked_sub(1);
+                            }
+                            _ => (),
+                        },
                     }
                 }
-                trace!("ran single step of the simulation");
+            }
 
-                if last_tick.elapsed() >= tick_rate {
-                    self.tick();
-                    last_tick = Instant::now();
-                }
+            //  Inspecting an earlier step holds the live run where it is
+            if self.state == AppState::Running
+                && self.cursor.is_none()
+                && !self.finished
+                && !self.paused
+            {
+                self.run_due_steps(io, workload, &mut next_step).await;
+            }
+            if self.end_screen_at.is_some_and(|at| Instant::now() >= at) {
+                self.end_screen_at = None;
+                self.state = match self.level_passed {
+                    true => AppState::LevelComplete,
+                    false => AppState::GameOver,
+                };
             }
 
             terminal.draw(|frame| {
-                self.draw(frame, seed);
+                self.draw(frame, io);
             })?;
         }
         Ok(())
     }
 
-    fn draw(&mut self, frame: &mut Frame, seed: u64) -> io::Result<()> {
+    fn draw(&mut self, frame: &mut Frame, io: &GameIO) {
         trace!("running the draw function");
+        let seed = self.seed;
         match self.state {
             AppState::StartScreen => self.render_start_screen(frame),
-            AppState::Running => self.render_game_screen(frame, seed),
+            AppState::Running => self.render_game_screen(frame, io, seed),
             AppState::GameOver => self.render_game_over_screen(frame),
+            AppState::LevelComplete => self.render_level_complete_screen(frame),
         };
-
-        Ok(())
     }
 
-    fn render_start_screen(&mut self, frame: &mut Frame) -> io::Result<()> {
+    fn render_start_screen(&mut self, frame: &mut Frame) {
         let area = frame.area();
 
         let title_art = vec![
@@ -318,13 +1092,14 @@ impl App {
             "",
             "Are you ready to test your error handling?",
             "",
-            "🎮 Press 'Enter' to start",
+            "🎮 Pick a level with ← →, type a seed or pick one with ↑ ↓, then press 'Enter'",
             "🚪 Press 'q' to quit",
+            "",
         ];
 
         let all_content = [title_art, robot_art, instructions].concat();
 
-        let styled_content = all_content
+        let mut styled_content = all_content
             .iter()
             .map(|&line| {
                 Line::styled(
@@ -336,6 +1111,45 @@ impl App {
             })
             .collect::<Vec<_>>();
 
+        let level = &self.levels[self.level];
+        styled_content.push(Line::styled(
+            format!(
+                "◀ Level {} of {}: {} ▶",
+                self.level + 1,
+                self.levels.len(),
+                level.name
+            ),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ));
+        styled_content.push(Line::styled(
+            format!(
+                "{}. Survive {} steps and shut down without losing data. {} unlocked.",
+                level.description, level.steps_to_pass, self.unlocked_levels
+            ),
+            Style::default().fg(Color::Yellow),
+        ));
+        styled_content.push(Line::from(""));
+        for (index, option) in self.seed_options().into_iter().enumerate() {
+            let label = match option {
+                SeedOption::Typed => format!("Seed: {}_", self.seed_input),
+                SeedOption::Failed(seed) => format!("Replay failed seed {}", seed),
+                SeedOption::Random => "Random seed".to_string(),
+            };
+            let mut style = Style::default().fg(Color::Green);
+            if option == SeedOption::Typed
+                && !self.seed_input.is_empty()
+                && self.seed_input.parse::<u64>().is_err()
+            {
+                style = style.fg(Color::Red);
+            }
+            if index == self.seed_selection {
+                style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+            }
+            styled_content.push(Line::styled(format!(" {} ", label), style));
+        }
+
         let paragraph = Paragraph::new(styled_content)
             .alignment(Alignment::Center)
             .block(
@@ -351,10 +1165,9 @@ impl App {
             );
 
         frame.render_widget(paragraph, area);
-        Ok(())
     }
 
-    fn render_game_over_screen(&self, frame: &mut Frame) -> io::Result<()> {
+    fn render_game_over_screen(&self, frame: &mut Frame) {
         let area = frame.area();
 
         let game_over_art = vec![
@@ -381,21 +1194,33 @@ impl App {
             r"    ████████████     ",
         ];
 
-        let reason = {
-            let mut str = "".to_string();
-            if let Some(reason) = &self.death_reason {
-                str = format!("⚠️  Reason: {}", reason);
-            } else {
-                str = "⚠️  Reason: Unknown error occurred".to_string();
-            }
-            str
+        let reason = match &self.death_reason {
+            Some(reason) => format!("⚠️  Reason: {}", reason),
+            None => "⚠️  Reason: Unknown error occurred".to_string(),
+        };
+        let seed = match self.probability_changes.len() {
+            0 => format!("🎲 Seed {} 🎲", self.seed),
+            changes => format!(
+                "🎲 Seed {} with {} probability changes 🎲",
+                self.seed, changes
+            ),
         };
+        let level = format!("Level {}: {}", self.level + 1, self.levels[self.level].name);
+        let [score, breakdown] = score_lines(&self.score());
         let death_message = vec![
             "",
             "💀 SIMULATION CRASHED 💀",
             "",
+            seed.as_str(),
+            "",
             reason.as_str(),
             "",
+            level.as_str(),
+            score.as_str(),
+            breakdown.as_str(),
+            "",
+            "Press 'r' to replay this seed",
+            "Press '←' to step back through the run",
             "Press 'Enter' to exit",
         ];
 
@@ -415,6 +1240,10 @@ impl App {
                 // Add blinking effect to the skull and "SIMULATION CRASHED" text
                 let style = if line.contains("💀") {
                     base_style.add_modifier(Modifier::SLOW_BLINK)
+                } else if line.contains("🎲") {
+                    base_style
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::REVERSED)
                 } else {
                     base_style
                 };
@@ -434,10 +1263,60 @@ impl App {
             );
 
         frame.render_widget(paragraph, area);
-        Ok(())
     }
 
-    fn render_game_screen(&mut self, frame: &mut Frame, seed: u64) -> io::Result<()> {
+    fn render_level_complete_screen(&self, frame: &mut Frame) {
+        let level = &self.levels[self.level];
+        let [score, breakdown] = score_lines(&self.score());
+        let next = match self.levels.get(self.level + 1) {
+            None => "That was the last level. Your pipeline survived everything!".to_string(),
+            Some(_) if self.level + 1 >= self.unlocked_levels => {
+                "Tuned runs are practice, play it untuned to unlock the next level".to_string()
+            }
+            Some(next) => format!(
+                "Press 'n' for level {}: {} ({})",
+                self.level + 2,
+                next.name,
+                next.description
+            ),
+        };
+        let lines = vec![
+            "".to_string(),
+            "🏆 LEVEL COMPLETE 🏆".to_string(),
+            "".to_string(),
+            format!("Level {}: {}", self.level + 1, level.name),
+            format!("🎲 Seed {} 🎲", self.seed),
+            "".to_string(),
+            score,
+            breakdown,
+            "".to_string(),
+            next,
+            "Press 'r' to replay this seed".to_string(),
+            "Press '←' to step back through the run".to_string(),
+            "Press 'Enter' to exit".to_string(),
+        ];
+        let style = Style::default()
+            .fg(Color::Green)
+            .add_modifier(Modifier::BOLD);
+        let styled_content = lines
+            .into_iter()
+            .map(|line| Line::styled(line, style))
+            .collect::<Vec<_>>();
+
+        let paragraph = Paragraph::new(styled_content)
+            .alignment(Alignment::Center)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(style)
+                    .title("Level Complete")
+                    .title_alignment(Alignment::Center),
+            );
+
+        frame.render_widget(paragraph, frame.area());
+    }
+
+    fn render_game_screen(&mut self, frame: &mut Frame, io: &GameIO, seed: u64) {
         let size = frame.area();
 
         //  Split the screen horizontally into two main sections (top & bottom)
@@ -458,21 +1337,51 @@ impl App {
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
             .split(top_split_layout[1]);
 
+        //  Split the bottom section into the topology and the status log
+        let bottom_split_layout = Layout::default()
+            .direction(ratatui::layout::Direction::Vertical)
+            .constraints([Constraint::Length(7), Constraint::Min(0)])
+            .split(main_layout[1]);
+
         let gauge_view = self.render_gauge_view();
         let app_view = self.render_app_view(seed);
-        let fault_view = self.render_fault_log();
+        let fault_view = if self.tuning {
+            self.render_tuning_panel(io, seed)
+        } else if self.inspecting_file {
+            self.render_file_inspector()
+        } else {
+            self.render_fault_log()
+        };
         let status_view = self.render_status_log();
 
         frame.render_widget(gauge_view, top_split_layout[0]);
         frame.render_widget(app_view, top_second_split_layout[0]);
         frame.render_widget(fault_view, top_second_split_layout[1]);
-        frame.render_widget(status_view, main_layout[1]);
-        Ok(())
+        self.render_topology(frame, bottom_split_layout[0]);
+        frame.render_widget(status_view, bottom_split_layout[1]);
     }
 
     fn render_app_view<'a>(&self, seed: u64) -> Paragraph<'a> {
+        if let Some(record) = self.cursor.and_then(|step| self.history.get(step)) {
+            return self.render_step_inspector(record, seed);
+        }
+        let record = self.selected_record().cloned().unwrap_or_default();
         let mut lines = vec![];
         lines.push(format!("Seed: {}", seed));
+        lines.push(format!(
+            "Step {}  Virtual time: {:?}",
+            record.step, record.virtual_time
+        ));
+        if let Some(circuits) = format_circuits(&record.circuit_states) {
+            lines.push(format!("Circuits: {}", circuits));
+        }
+        let [score, breakdown] = score_lines(&self.score());
+        lines.push(format!("{}: {}", score, breakdown));
+        if self.tuned() {
+            lines.push("Tuned run: practice only, levels won't unlock".to_string());
+        }
+        lines.push("p pause  s single step  1/2/3 speed 1x/10x/max".to_string());
+        lines.push("t tune faults  f inspect file  ← step back through the run".to_string());
 
         // Base castle structure - middle section that won't change
         // let mut castle_structure = vec![
@@ -634,10 +1543,145 @@ impl App {
     //         .block(Block::default().borders(Borders::ALL).title("Application"))
     // }
 
-    fn render_gauge_view<'a>(&self) -> ratatui::widgets::Gauge {
-        let progress = (self.tick_count % 100) as u16;
+    /// Everything recorded for one earlier step, shown in place of the castle while scrubbing.
+    fn render_step_inspector<'a>(&self, record: &StepRecord, seed: u64) -> Paragraph<'a> {
+        let heading = Style::default()
+            .fg(Color::Yellow)
+            .add_modifier(Modifier::BOLD);
+        let mut lines = vec![
+            Line::from(format!("Seed: {}", seed)),
+            Line::styled(
+                format!(
+                    "⏪ Step {} of {}  Virtual time: {:?}",
+                    record.step,
+                    self.history.len() - 1,
+                    record.virtual_time
+                ),
+                heading,
+            ),
+        ];
+        if let Some(circuits) = format_circuits(&record.circuit_states) {
+            lines.push(Line::from(format!("Circuits: {}", circuits)));
+        }
+        let faults = match record.faults.is_empty() {
+            true => "none".to_string(),
+            false => record
+                .faults
+                .iter()
+                .map(FaultType::to_log_message)
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        lines.push(Line::from(format!("Faults injected: {}", faults)));
+        if let Some(error) = &record.error {
+            lines.push(Line::styled(
+                format!("Failed: {}", error),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::styled("Status", heading));
+        lines.extend(record.status.iter().map(|msg| Line::from(msg.clone())));
+        lines.push(Line::from(""));
+        lines.push(Line::styled(
+            format!(
+                "Output file ({} bytes unsynced)",
+                record.file.unsynced_bytes()
+            ),
+            heading,
+        ));
+        lines.extend(
+            record
+                .file
+                .tail
+                .iter()
+                .map(|entry| Line::from(entry.payload.clone())),
+        );
+        lines.push(Line::from(""));
+        lines.push(Line::from(
+            "← → step  [ ] previous/next fault  Enter resume from here  End back to live",
+        ));
+
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(heading)
+                .title("Inspecting"),
+        )
+    }
+
+    /// Kafka -> processor -> Redis -> file, each coloured by its health.
+    fn render_topology(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Topology (last {} steps)", HEALTH_WINDOW));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let mut constraints = vec![];
+        for index in 0..Component::ALL.len() {
+            if index > 0 {
+                constraints.push(Constraint::Length(5));
+            }
+            constraints.push(Constraint::Ratio(1, Component::ALL.len() as u32));
+        }
+        let columns = Layout::default()
+            .direction(ratatui::layout::Direction::Horizontal)
+            .constraints(constraints)
+            .split(inner);
+
+        for (index, node) in self.component_health().iter().enumerate() {
+            if index > 0 {
+                let arrow = Paragraph::new(vec![Line::from(""), Line::from(" ──▶ ")]);
+                frame.render_widget(arrow, columns[index * 2 - 1]);
+            }
+            let style = Style::default().fg(node.health.color());
+            let errors = match node.calls {
+                0 => format!("errors {}", node.errors),
+                calls => format!(
+                    "errors {}/{} ({}%)",
+                    node.errors,
+                    calls,
+                    node.errors * 100 / calls
+                ),
+            };
+            let lines = vec![
+                Line::from(node.state.clone()),
+                Line::from(errors),
+                Line::from(format!("retries {}", node.retries)),
+            ];
+            let widget = Paragraph::new(lines).style(style).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(style.add_modifier(Modifier::BOLD))
+                    .title(node.component.name()),
+            );
+            frame.render_widget(widget, columns[index * 2]);
+        }
+    }
+
+    fn render_gauge_view(&self) -> ratatui::widgets::Gauge<'_> {
+        if let Some(step) = self.cursor {
+            let last = self.history.len().saturating_sub(1).max(1);
+            return ratatui::widgets::Gauge::default()
+                .block(Block::default().title(format!("Step {} of {}", step, last)))
+                .gauge_style(Style::default().fg(Color::Yellow).bg(Color::Black))
+                .percent((step * 100 / last) as u16);
+        }
+        let level = &self.levels[self.level];
+        let steps = self.score().steps;
+        let progress = (steps * 100 / level.steps_to_pass).min(100) as u16;
+        let title = format!(
+            "Level {}: {}  {} of {} steps ({}{})",
+            self.level + 1,
+            level.name,
+            steps,
+            level.steps_to_pass,
+            if self.paused { "paused, " } else { "" },
+            self.speed.label()
+        );
         ratatui::widgets::Gauge::default()
-            .block(Block::default().title("Iterations"))
+            .block(Block::default().title(title))
             .gauge_style(
                 Style::default()
                     .fg(Color::Green)
@@ -647,10 +1691,172 @@ impl App {
             .percent(progress)
     }
 
+    /// The output file as of the selected step, coloured by what a crash would do to it.
+    fn render_file_inspector<'a>(&self) -> Paragraph<'a> {
+        let heading = Style::default().add_modifier(Modifier::BOLD);
+        let mut lines = vec![Line::from("f back to the fault log")];
+        let Some(record) = self.selected_record() else {
+            return Paragraph::new(lines);
+        };
+        let file = &record.file;
+        if !record.file_open {
+            lines.push(Line::styled(
+                "No file open",
+                Style::default().fg(Color::Red),
+            ));
+        }
+        lines.push(Line::from(format!(
+            "{} bytes written, {} synced, read position {}",
+            file.len, file.synced_len, file.read_position
+        )));
+        let crash = match file.unsynced_bytes() {
+            0 => Line::styled(
+                "A crash now loses nothing",
+                Style::default().fg(Color::Green),
+            ),
+            bytes => Line::styled(
+                format!(
+                    "A crash now loses up to {} bytes: {} records, {} more left torn",
+                    bytes, file.lost_records, file.torn_records
+                ),
+                Style::default().fg(Color::Yellow),
+            ),
+        };
+        lines.push(crash);
+        if file.valid_len < file.len {
+            lines.push(Line::styled(
+                format!(
+                    "Corrupt tail: {} bytes after offset {} don't frame a record",
+                    file.len - file.valid_len,
+                    file.valid_len
+                ),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ));
+        }
+
+        lines.push(Line::from(""));
+        lines.push(Line::styled("Last records", heading));
+        for entry in &file.tail {
+            let mark = match entry.durability {
+                Durability::Synced => "synced",
+                Durability::Torn => "torn by a crash",
+                Durability::Unsynced => "lost by a crash",
+            };
+            lines.push(Line::styled(
+                format!(
+                    "{:>8} {:>4} B  {:<16} {}",
+                    entry.offset, entry.framed_len, mark, entry.payload
+                ),
+                Style::default().fg(entry.durability.color()),
+            ));
+        }
+
+        lines.push(Line::from(""));
+        lines.push(Line::from(vec![
+            Span::styled("Tail  ", heading),
+            Span::styled("synced  ", Style::default().fg(Color::Green)),
+            Span::styled("unsynced  ", Style::default().fg(Color::Yellow)),
+            Span::styled("corrupt", Style::default().fg(Color::Red)),
+        ]));
+        for (row, chunk) in file.tail_bytes.chunks(16).enumerate() {
+            let start = file.tail_offset + row * 16;
+            let mut spans = vec![Span::raw(format!("{:>8}  ", start))];
+            for (index, byte) in chunk.iter().enumerate() {
+                spans.push(Span::styled(
+                    format!("{:02x} ", byte),
+                    Style::default().fg(file.byte_color(start + index)),
+                ));
+            }
+            spans.push(Span::raw(" ".repeat(3 * (16 - chunk.len()) + 1)));
+            for (index, byte) in chunk.iter().enumerate() {
+                let shown = match byte.is_ascii_graphic() || *byte == b' ' {
+                    true => *byte as char,
+                    false => '.',
+                };
+                spans.push(Span::styled(
+                    shown.to_string(),
+                    Style::default().fg(file.byte_color(start + index)),
+                ));
+            }
+            lines.push(Line::from(spans));
+        }
+
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("File inspector (step {})", record.step)),
+        )
+    }
+
+    /// The current probability of every fault that can fire, plus the changes made so far.
+    fn render_tuning_panel<'a>(&self, io: &GameIO, seed: u64) -> Paragraph<'a> {
+        let mut lines = vec![Line::from(
+            "↑ ↓ select  + - adjust  t back to the fault log",
+        )];
+        for (index, fault) in tunable_faults().iter().enumerate() {
+            let probability = io.inner().fault_probability(fault);
+            let filled = (probability * 20.0).round() as usize;
+            let line = format!(
+                "{} {:<28} {:>4.0}% {}{}",
+                if index == self.tuning_selection {
+                    ">"
+                } else {
+                    " "
+                },
+                fault.to_log_message(),
+                probability * 100.0,
+                "█".repeat(filled),
+                "░".repeat(20 - filled)
+            );
+            let style = match index == self.tuning_selection {
+                true => Style::default().fg(Color::Black).bg(Color::Yellow),
+                false => Style::default().fg(Color::Yellow),
+            };
+            lines.push(Line::styled(line, style));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::styled(
+            format!(
+                "Seed {} plus these {} changes replays this run",
+                seed,
+                self.probability_changes.len()
+            ),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        let changes = &self.probability_changes;
+        lines.extend(
+            changes[changes.len().saturating_sub(10)..]
+                .iter()
+                .map(|change| {
+                    Line::from(format!(
+                        "  before step {}: {} -> {:.0}%",
+                        change.step,
+                        change.fault.to_log_message(),
+                        change.probability * 100.0
+                    ))
+                }),
+        );
+
+        Paragraph::new(lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title("Fault probabilities"),
+        )
+    }
+
     fn render_fault_log<'a>(&self) -> Paragraph<'a> {
         trace!("rendering the fault log");
-        let styled_faults: Vec<Line> = self
-            .fault_log
+        let mut fault_log: Vec<String> = self
+            .visible_history()
+            .iter()
+            .rev()
+            .flat_map(|record| record.faults.iter().rev())
+            .take(20)
+            .map(FaultType::to_log_message)
+            .collect();
+        fault_log.reverse();
+        let styled_faults: Vec<Line> = fault_log
             .iter()
             .flat_map(|msg| {
                 // Create two lines for each fault for bigger appearance
@@ -680,8 +1886,15 @@ impl App {
 
     fn render_status_log<'a>(&self) -> Paragraph<'a> {
         trace!("rendering the status log");
-        let styled_statuses: Vec<Line> = self
-            .status_log
+        let mut status_log: Vec<&String> = self
+            .visible_history()
+            .iter()
+            .rev()
+            .flat_map(|record| record.status.iter().rev())
+            .take(50)
+            .collect();
+        status_log.reverse();
+        let styled_statuses: Vec<Line> = status_log
             .iter()
             .enumerate()
             .flat_map(|(idx, msg)| {
@@ -696,7 +1909,7 @@ impl App {
                         Style::default()
                             .fg(Color::Green)
                             .add_modifier(Modifier::BOLD)
-                            .add_modifier(if idx >= self.status_log.len().saturating_sub(3) {
+                            .add_modifier(if idx >= status_log.len().saturating_sub(3) {
                                 Modifier::RAPID_BLINK
                             } else {
                                 Modifier::empty()
@@ -707,7 +1920,7 @@ impl App {
             .collect();
 
         Paragraph::new(styled_statuses)
-            .scroll((self.status_log.len().saturating_sub(8) as u16, 0))
+            .scroll((status_log.len().saturating_sub(8) as u16, 0))
             .block(
                 Block::default()
                     .borders(Borders::ALL)
@@ -716,3 +1929,26 @@ impl App {
             )
     }
 }
+
+fn format_circuits(states: &[(&'static str, CircuitState)]) -> Option<String> {
+    if states.is_empty() {
+        return None;
+    }
+    let circuits = states
+        .iter()
+        .map(|(name, state)| format!("{}: {}", name, state))
+        .collect::<Vec<_>>()
+        .join("  ");
+    Some(circuits)
+}
+
+/// The total score and what it is made up of.
+fn score_lines(score: &Score) -> [String; 2] {
+    [
+        format!("Score {}", score.total()),
+        format!(
+            "{} steps survived, {} faults absorbed, {} records lost",
+            score.steps, score.faults_absorbed, score.records_lost
+        ),
+    ]
+}
@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+
+use crate::{CircuitBreaker, Errors, IO};
+
+/// A unit of application logic that can be driven against any `IO`, real or simulated.
+///
+/// The simulator, the seed sweeper and the TUI only talk to this trait, so a consumer can
+/// be plugged into all of them without touching the drivers.
+#[async_trait]
+pub trait Workload: Send {
+    /// Connects to whatever the workload depends on. Called once before the first step.
+    async fn init(&mut self, io: &mut dyn IO) -> Result<(), Errors>;
+
+    /// Runs a single iteration of the workload.
+    async fn step(&mut self, io: &mut dyn IO) -> Result<(), Errors>;
+
+    /// Verifies the workload's invariants against the state it has observed so far.
+    async fn check(&mut self, io: &mut dyn IO) -> Result<(), Errors>;
+
+    /// The full invariant check run once at the end of a simulation, after `shutdown`.
+    async fn verify(&mut self, io: &mut dyn IO) -> Result<(), Errors> {
+        self.check(io).await
+    }
+
+    /// Stops consuming and makes whatever is in flight durable. Fails if anything had to be
+    /// left behind.
+    async fn shutdown(&mut self, _io: &mut dyn IO) -> Result<(), Errors> {
+        Ok(())
+    }
+
+    /// Human readable lines describing the last `init` or `step`, shown in the TUI.
+    fn status_messages(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Circuit breakers guarding the workload's dependencies, for display.
+    fn circuit_breakers(&self) -> Vec<&CircuitBreaker> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,50 @@
+//! `RealFile` against the host filesystem. This runs as its own test binary because it
+//! lowers the process's file size limit to make writes fail partway.
+
+use std::path::PathBuf;
+
+use dst::{ErrorKind, File, RealFile};
+
+/// Caps the size of every file this process writes at `bytes`. A write that crosses the cap
+/// stores what fits and then fails, rather than the process being killed.
+fn limit_file_size(bytes: u64) {
+    unsafe {
+        libc::signal(libc::SIGXFSZ, libc::SIG_IGN);
+        let mut limit: libc::rlimit = std::mem::zeroed();
+        assert_eq!(libc::getrlimit(libc::RLIMIT_FSIZE, &mut limit), 0);
+        limit.rlim_cur = bytes;
+        assert_eq!(libc::setrlimit(libc::RLIMIT_FSIZE, &limit), 0);
+    }
+}
+
+fn scratch_file() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dst-real-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("output.txt")
+}
+
+#[tokio::test]
+async fn failed_write_leaves_no_partial_record_behind() {
+    let path = scratch_file();
+    limit_file_size(256);
+
+    let mut file = RealFile::open(&path).await.unwrap();
+    file.write("first").await.unwrap();
+    let error = file.write(&"x".repeat(1024)).await.unwrap_err();
+    assert_eq!(error, ErrorKind::FileWriteError);
+    assert_eq!(
+        std::fs::metadata(&path).unwrap().len(),
+        file.size() as u64,
+        "part of the failed write is still in the file"
+    );
+    file.write("second").await.unwrap();
+    file.fsync().await.unwrap();
+
+    let mut reopened = RealFile::open(&path).await.unwrap();
+    assert_eq!(reopened.recover().await.unwrap(), 0);
+    assert_eq!(
+        reopened.read_last_n_entries(10).await.unwrap(),
+        ["first", "second"]
+    );
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,84 @@
+//! Helpers shared by the scenarios.
+
+use std::future::Future;
+
+use dst::{
+    frame, FaultType, FileFaultType, FileSnapshot, OutputRecord, SimulatedIO, SimulationControl,
+    Workload, IO,
+};
+
+/// Seeds every scenario runs with. Set `SEED` to run just that one, e.g. to reproduce a
+/// failure.
+pub fn seeds() -> Vec<u64> {
+    match std::env::var("SEED") {
+        Ok(seed) => vec![seed.parse().expect("SEED must be a number")],
+        Err(_) => (0..5).collect(),
+    }
+}
+
+/// Runs `scenario` once per seed, failing the test with the first seed it fails for.
+pub async fn for_each_seed<F, Fut>(scenario: F)
+where
+    F: Fn(u64) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    for seed in seeds() {
+        if let Err(reason) = scenario(seed).await {
+            panic!("failed with seed {}: {}", seed, reason);
+        }
+    }
+}
+
+pub fn set_write_failures(io: &mut SimulatedIO, probability: f64) {
+    io.set_fault_probability(
+        FaultType::FileFaultType(FileFaultType::FileWriteFailure),
+        probability,
+    );
+}
+
+pub async fn init<W: Workload>(io: &mut SimulatedIO, workload: &mut W) -> Result<(), String> {
+    workload
+        .init(io)
+        .await
+        .map_err(|e| format!("init failed at {:?}: {}", io.now(), e))
+}
+
+pub async fn step<W: Workload>(io: &mut SimulatedIO, workload: &mut W) -> Result<(), String> {
+    workload
+        .step(io)
+        .await
+        .map_err(|e| format!("step failed at {:?}: {}", io.now(), e))?;
+    workload
+        .check(io)
+        .await
+        .map_err(|e| format!("check failed at {:?}: {}", io.now(), e))
+}
+
+/// Runs `count` steps, stopping at the first one that fails.
+pub async fn steps<W: Workload>(
+    io: &mut SimulatedIO,
+    workload: &mut W,
+    count: usize,
+) -> Result<(), String> {
+    for _ in 0..count {
+        step(io, workload).await?;
+    }
+    Ok(())
+}
+
+/// Kafka offsets of every record in the output log, in file order.
+pub fn output_offsets(io: &SimulatedIO) -> Vec<i64> {
+    io.segment_snapshots()
+        .iter()
+        .flat_map(|segment| frame::scan(&segment.contents).records)
+        .filter_map(|(_, payload)| OutputRecord::decode(&String::from_utf8_lossy(&payload)))
+        .map(|record| record.offset)
+        .collect()
+}
+
+pub fn record_count(segments: &[FileSnapshot]) -> usize {
+    segments
+        .iter()
+        .map(|segment| frame::scan(&segment.contents).records.len())
+        .sum()
+}
@@ -0,0 +1,240 @@
+//! Getting records from Kafka into the output exactly once: batching, offset commits, the
+//! write queue and shutting down.
+
+use std::path::Path;
+use std::time::Duration;
+
+use rand::Rng;
+use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+use tracing::info;
+
+use dst::simulator::{run_workload, RunLimits};
+use dst::{
+    BatchPolicy, ErrorKind, PipelineWorkload, SimulatedIO, SimulationControl, WriteQueue, IO,
+};
+
+use crate::common::{for_each_seed, init, output_offsets, set_write_failures, step, steps};
+
+/// Fails if the output isn't every offset from 0 onwards exactly once.
+fn check_contiguous(io: &SimulatedIO, context: &str) -> Result<(), String> {
+    let offsets = output_offsets(io);
+    let expected: Vec<i64> = (0..offsets.len() as i64).collect();
+    if offsets != expected {
+        return Err(format!("{} the file holds {:?}", context, offsets));
+    }
+    Ok(())
+}
+
+/// Records go out in batches that are synced before their offsets are committed. Whenever
+/// the process crashes, every committed offset must be in the file, and the restarted
+/// consumer must fill in the rest without gaps or duplicates.
+#[tokio::test]
+async fn group_commit() {
+    for_each_seed(|seed| async move {
+        let max_delay = Duration::from_secs(1);
+        let batching = BatchPolicy::default()
+            .with_max_records(4)
+            .with_max_delay(max_delay);
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut workload = PipelineWorkload::new("config_key").with_batching(batching.clone());
+        init(&mut io, &mut workload).await?;
+
+        steps(&mut io, &mut workload, 3).await?;
+        if io.committed_offset().is_some() || !output_offsets(&io).is_empty() {
+            return Err("flushed before the batch was full".to_string());
+        }
+        step(&mut io, &mut workload).await?;
+        if io.committed_offset() != Some(3) || output_offsets(&io) != vec![0, 1, 2, 3] {
+            return Err(format!(
+                "full batch not committed: committed {:?}, file holds {:?}",
+                io.committed_offset(),
+                output_offsets(&io)
+            ));
+        }
+
+        step(&mut io, &mut workload).await?;
+        io.advance_clock(max_delay);
+        step(&mut io, &mut workload).await?;
+        if io.committed_offset() != Some(5) {
+            return Err(format!(
+                "batch not flushed after {:?}, committed {:?}",
+                max_delay,
+                io.committed_offset()
+            ));
+        }
+
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        for round in 0..10 {
+            steps(&mut io, &mut workload, rng.gen_range(1..10)).await?;
+            let committed = io.committed_offset().unwrap_or(-1);
+            io.crash();
+            workload = PipelineWorkload::new("config_key").with_batching(batching.clone());
+            init(&mut io, &mut workload)
+                .await
+                .map_err(|e| format!("after crash {}: {}", round, e))?;
+            let offsets = output_offsets(&io);
+            let expected: Vec<i64> = (0..offsets.len() as i64).collect();
+            if offsets != expected || (offsets.len() as i64) <= committed {
+                return Err(format!(
+                    "after crash {} with offset {} committed the file holds {:?}",
+                    round, committed, offsets
+                ));
+            }
+        }
+        Ok(())
+    })
+    .await;
+}
+
+/// Writes keep failing: the queue must stop at its capacity and pause consumption, then
+/// drain in order. With a spill file the overflow goes to disk instead, has its offsets
+/// committed, and is picked up again after a crash without gaps or duplicates.
+#[tokio::test]
+async fn write_queue() {
+    for_each_seed(|seed| async move {
+        let capacity = 3;
+        let batching = BatchPolicy::default().with_max_records(1);
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut workload = PipelineWorkload::new("config_key")
+            .with_batching(batching.clone())
+            .with_write_queue(WriteQueue::new(capacity));
+        init(&mut io, &mut workload).await?;
+        steps(&mut io, &mut workload, 3).await?;
+
+        set_write_failures(&mut io, 1.0);
+        steps(&mut io, &mut workload, 10).await?;
+        if !workload.backpressure() || workload.queued_writes() != capacity {
+            return Err(format!(
+                "expected consumption paused with {} records queued, {} are queued",
+                capacity,
+                workload.queued_writes()
+            ));
+        }
+        set_write_failures(&mut io, 0.0);
+        steps(&mut io, &mut workload, 3).await?;
+        if workload.backpressure() || workload.queued_writes() != 0 {
+            return Err(format!(
+                "still paused after writes recovered, {} records queued",
+                workload.queued_writes()
+            ));
+        }
+        check_contiguous(&io, "after draining the queue")?;
+
+        let spill_path = Path::new("output.spill");
+        let queue = || WriteQueue::new(capacity).with_spill_file(spill_path);
+        let mut workload = PipelineWorkload::new("config_key")
+            .with_batching(batching.clone())
+            .with_write_queue(queue());
+        init(&mut io, &mut workload).await?;
+        set_write_failures(&mut io, 0.5);
+        for _ in 0..1000 {
+            step(&mut io, &mut workload).await?;
+            let in_memory = workload.queued_writes() - workload.spilled_writes();
+            if in_memory > capacity {
+                return Err(format!("{} records held in memory", in_memory));
+            }
+            if workload.spilled_writes() > 0 {
+                break;
+            }
+        }
+        let spilled = workload.spilled_writes();
+        if spilled == 0 {
+            return Err("nothing was spilled".to_string());
+        }
+        info!("spilled {} records at {:?}", spilled, io.now());
+
+        let committed = io.committed_offset().unwrap_or(-1);
+        io.crash();
+        set_write_failures(&mut io, 0.0);
+        let mut workload = PipelineWorkload::new("config_key")
+            .with_batching(batching)
+            .with_write_queue(queue());
+        init(&mut io, &mut workload).await?;
+        if workload.spilled_writes() != spilled {
+            return Err(format!(
+                "{} records were spilled but {} recovered",
+                spilled,
+                workload.spilled_writes()
+            ));
+        }
+        steps(&mut io, &mut workload, 3).await?;
+        if workload.queued_writes() != 0 || io.file_system().exists(spill_path) {
+            return Err(format!(
+                "spill file not drained, {} records queued",
+                workload.queued_writes()
+            ));
+        }
+        check_contiguous(&io, "after draining the spill file")?;
+        if (output_offsets(&io).len() as i64) <= committed {
+            return Err(format!(
+                "offset {} was committed before the crash but the file stops at {:?}",
+                committed,
+                output_offsets(&io).last()
+            ));
+        }
+        Ok(())
+    })
+    .await;
+}
+
+/// A shutdown arrives mid-batch: the run must stop, write and sync everything it consumed
+/// and commit the last offset. When writes keep failing it must report what it left behind.
+#[tokio::test]
+async fn graceful_shutdown() {
+    for_each_seed(|seed| async move {
+        let batching = BatchPolicy::default()
+            .with_max_records(8)
+            .with_max_delay(Duration::from_secs(60));
+        let max_steps = 5_000;
+        let limits = RunLimits::default().with_max_steps(max_steps);
+        let mut io = SimulatedIO::without_faults(seed);
+        io.schedule_shutdown(io.now() + Duration::from_secs(1));
+        let mut workload = PipelineWorkload::new("config_key").with_batching(batching.clone());
+        let (steps, result) = run_workload(&mut io, &mut workload, &limits).await;
+        result.map_err(|e| format!("shutdown failed after {} steps: {}", steps, e))?;
+        if steps == max_steps {
+            return Err(format!("still running after {} steps", steps));
+        }
+        if workload.queued_writes() != 0 || workload.unacknowledged_writes() != 0 {
+            return Err(format!(
+                "shut down with {} records queued and {} unacknowledged",
+                workload.queued_writes(),
+                workload.unacknowledged_writes()
+            ));
+        }
+        check_contiguous(&io, "after shutting down")?;
+        let offsets = output_offsets(&io);
+        if io.committed_offset() != offsets.last().copied() {
+            return Err(format!(
+                "committed {:?} but the file ends at {:?}",
+                io.committed_offset(),
+                offsets.last()
+            ));
+        }
+        info!(
+            "shut down after {} steps with {} records",
+            steps,
+            offsets.len()
+        );
+
+        //  Nothing was lost, so a crash right after shutting down changes nothing
+        io.crash();
+        if output_offsets(&io) != offsets {
+            return Err("records written before the shutdown were lost in a crash".to_string());
+        }
+
+        let mut io = SimulatedIO::without_faults(seed);
+        io.schedule_shutdown(io.now() + Duration::from_secs(1));
+        set_write_failures(&mut io, 1.0);
+        let mut workload = PipelineWorkload::new("config_key").with_batching(batching);
+        let (_, result) = run_workload(&mut io, &mut workload, &limits).await;
+        match result {
+            Err(e) if e == ErrorKind::ShutdownIncomplete => Ok(()),
+            other => Err(format!(
+                "expected the shutdown to report unwritten records, got {:?}",
+                other
+            )),
+        }
+    })
+    .await;
+}
@@ -0,0 +1,11 @@
+//! Scripted simulations that push a workload through a specific sequence of faults and
+//! verify how it reacts. Each one runs with a handful of seeds, or just `SEED` when set:
+//!
+//! ```text
+//! SEED=7 cargo test --test scenarios kafka_outage
+//! ```
+
+mod common;
+mod delivery;
+mod outages;
+mod storage;
@@ -0,0 +1,216 @@
+//! Dependencies that fail or hand out bad data: the pipeline must wait them out or set the
+//! data aside rather than fail.
+
+use std::path::Path;
+use std::time::Duration;
+
+use tracing::info;
+
+use dst::{
+    CircuitState, ConfigCache, FaultType, KafkaMessage, PipelineWorkload, SimulatedIO,
+    SimulationControl, Workload, IO,
+};
+
+use crate::common::{for_each_seed, init, output_offsets, set_write_failures, step, steps};
+
+fn breaker_state<W: Workload>(workload: &W, name: &str) -> Option<CircuitState> {
+    workload
+        .circuit_breakers()
+        .into_iter()
+        .find(|breaker| breaker.name() == name)
+        .map(|breaker| breaker.state())
+}
+
+/// Steps the workload until the named breaker reaches `state`, failing if any step errors
+/// or the breaker doesn't get there within `max_steps`.
+async fn step_until<W: Workload>(
+    io: &mut SimulatedIO,
+    workload: &mut W,
+    breaker: &str,
+    state: CircuitState,
+    max_steps: usize,
+) -> Result<usize, String> {
+    for steps in 1..=max_steps {
+        step(io, workload).await?;
+        if breaker_state(workload, breaker) == Some(state) {
+            return Ok(steps);
+        }
+    }
+    Err(format!(
+        "{} breaker still {:?} after {} steps, expected {}",
+        breaker,
+        breaker_state(workload, breaker),
+        max_steps,
+        state
+    ))
+}
+
+/// The connection to Kafka keeps dropping: the breaker must open instead of failing the
+/// pipeline. Once the faults stop it goes half-open after its cool down, and the trial read
+/// closes it again.
+#[tokio::test]
+async fn kafka_outage() {
+    for_each_seed(|seed| async move {
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut workload = PipelineWorkload::new("config_key");
+        init(&mut io, &mut workload).await?;
+
+        io.set_fault_probability(FaultType::KafkaConnectionFailure, 1.0);
+        let steps = step_until(&mut io, &mut workload, "kafka", CircuitState::Open, 20).await?;
+        info!(
+            "kafka breaker opened after {} steps at {:?}",
+            steps,
+            io.now()
+        );
+
+        io.set_fault_probability(FaultType::KafkaConnectionFailure, 0.0);
+        let steps = step_until(&mut io, &mut workload, "kafka", CircuitState::HalfOpen, 50).await?;
+        info!(
+            "kafka breaker half-open after {} steps at {:?}",
+            steps,
+            io.now()
+        );
+        step_until(&mut io, &mut workload, "kafka", CircuitState::Closed, 1).await?;
+        Ok(())
+    })
+    .await;
+}
+
+/// Kafka hands out messages that can never be processed, while writes keep failing. Each
+/// one must end up in the dead-letter file exactly once, and together with the output it
+/// must account for every offset consumed.
+#[tokio::test]
+async fn malformed_messages() {
+    for_each_seed(|seed| async move {
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut workload = PipelineWorkload::new("config_key");
+        init(&mut io, &mut workload).await?;
+
+        io.set_fault_probability(FaultType::KafkaReadFailure, 0.2);
+        set_write_failures(&mut io, 0.1);
+        steps(&mut io, &mut workload, 200).await?;
+        io.disable_faults();
+        workload
+            .shutdown(&mut io)
+            .await
+            .map_err(|e| format!("shutdown failed: {}", e))?;
+        workload
+            .verify(&mut io)
+            .await
+            .map_err(|e| format!("verify failed: {}", e))?;
+
+        let dead_lettered: Vec<i64> = io
+            .read_records(Path::new("dead-letter.txt"))
+            .await
+            .map_err(|e| format!("failed to read the dead letters: {}", e))?
+            .iter()
+            .filter_map(|line| serde_json::from_str::<KafkaMessage>(line).ok())
+            .map(|message| message.offset)
+            .collect();
+        if dead_lettered.is_empty() {
+            return Err("no message was dead-lettered".to_string());
+        }
+        let mut offsets = [output_offsets(&io), dead_lettered.clone()].concat();
+        offsets.sort_unstable();
+        let expected: Vec<i64> = (0..offsets.len() as i64).collect();
+        if offsets != expected {
+            return Err(format!(
+                "output and dead letters together hold offsets {:?}",
+                offsets
+            ));
+        }
+        info!(
+            "dead-lettered {} of {} messages",
+            dead_lettered.len(),
+            offsets.len()
+        );
+        Ok(())
+    })
+    .await;
+}
+
+/// Redis stops answering: the breaker must open instead of failing the pipeline, and close
+/// again once Redis is back. Config caching is off so every step goes to Redis.
+#[tokio::test]
+async fn redis_outage() {
+    for_each_seed(|seed| async move {
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut workload =
+            PipelineWorkload::new("config_key").with_config_cache(ConfigCache::disabled());
+        init(&mut io, &mut workload).await?;
+
+        io.set_fault_probability(FaultType::RedisReadFailure, 1.0);
+        let steps = step_until(&mut io, &mut workload, "redis", CircuitState::Open, 20).await?;
+        info!(
+            "redis breaker opened after {} steps at {:?}",
+            steps,
+            io.now()
+        );
+
+        io.set_fault_probability(FaultType::RedisReadFailure, 0.0);
+        step_until(&mut io, &mut workload, "redis", CircuitState::Closed, 50).await?;
+        Ok(())
+    })
+    .await;
+}
+
+/// Redis goes away after the config has been cached: the pipeline keeps processing on the
+/// stale value until the background refresh gets through again. Once the staleness window
+/// runs out it waits for Redis instead.
+#[tokio::test]
+async fn stale_config() {
+    for_each_seed(|seed| async move {
+        let ttl = Duration::from_secs(5);
+        let max_staleness = Duration::from_secs(30);
+        let refresh_retry = Duration::from_secs(1);
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut workload = PipelineWorkload::new("config_key").with_config_cache(
+            ConfigCache::new(ttl, max_staleness).with_refresh_retry(refresh_retry),
+        );
+        init(&mut io, &mut workload).await?;
+        step(&mut io, &mut workload).await?;
+
+        io.set_fault_probability(FaultType::RedisReadFailure, 1.0);
+        io.advance_clock(ttl);
+        for _ in 0..5 {
+            step(&mut io, &mut workload).await?;
+            if !workload.serving_stale_config() {
+                return Err(format!("not serving stale config at {:?}", io.now()));
+            }
+        }
+        //  Only the background refresh goes to Redis, not every record served the stale value
+        if breaker_state(&workload, "redis") != Some(CircuitState::Closed) {
+            return Err(
+                "serving stale config kept calling Redis and opened its breaker".to_string(),
+            );
+        }
+
+        io.set_fault_probability(FaultType::RedisReadFailure, 0.0);
+        io.advance_clock(refresh_retry);
+        step(&mut io, &mut workload).await?;
+        if workload.serving_stale_config() {
+            return Err(format!(
+                "background refresh didn't replace the stale config at {:?}",
+                io.now()
+            ));
+        }
+
+        io.set_fault_probability(FaultType::RedisReadFailure, 1.0);
+        io.advance_clock(ttl + max_staleness);
+        step(&mut io, &mut workload).await?;
+        if workload.serving_stale_config() {
+            return Err(format!(
+                "still serving stale config past the staleness window at {:?}",
+                io.now()
+            ));
+        }
+
+        io.set_fault_probability(FaultType::RedisReadFailure, 0.0);
+        steps(&mut io, &mut workload, 2).await?;
+        if workload.serving_stale_config() {
+            return Err("still serving stale config after Redis recovered".to_string());
+        }
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,401 @@
+//! The output log and the simulated filesystem under it: crashes, rotation, full disks and
+//! directory metadata.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+use tracing::info;
+
+use dst::{
+    frame, BatchPolicy, Errors, FaultType, File, FileFaultType, PipelineWorkload, RotationPolicy,
+    SimulatedFile, SimulatedFileSystem, SimulatedIO, SimulationControl, IO,
+};
+
+use crate::common::{for_each_seed, init, record_count, step, steps};
+
+/// The process crashes with unsynced records in the file, leaving part of the last one on
+/// disk. Reopening the file must cut the torn tail off and keep every synced record.
+#[tokio::test]
+async fn torn_write() {
+    for_each_seed(|seed| async move {
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut workload = PipelineWorkload::new("config_key")
+            .with_batching(BatchPolicy::default().with_max_records(5));
+        init(&mut io, &mut workload).await?;
+        steps(&mut io, &mut workload, 5).await?;
+        //  The next batch makes it to the file but the process dies before syncing it
+        let batch: Vec<String> = (0..3).map(|i| format!("unsynced record {}", i)).collect();
+        io.write_batch_to_file(&batch)
+            .await
+            .map_err(|e| format!("write failed: {}", e))?;
+        io.crash();
+        let torn = io.file_snapshot().unwrap_or_default();
+        info!(
+            "crashed with {} bytes on disk, {} of them synced",
+            torn.contents.len(),
+            torn.synced_contents.len()
+        );
+
+        let mut workload = PipelineWorkload::new("config_key");
+        init(&mut io, &mut workload).await?;
+        let recovered = io.file_snapshot().unwrap_or_default();
+        let scan = frame::scan(&recovered.contents);
+        if scan.valid_len != recovered.contents.len() {
+            return Err(format!(
+                "{} corrupt bytes left after recovery",
+                recovered.contents.len() - scan.valid_len
+            ));
+        }
+        if scan.records.len() < 5 {
+            return Err(format!(
+                "only {} records survived the crash, expected at least the 5 synced ones",
+                scan.records.len()
+            ));
+        }
+        steps(&mut io, &mut workload, 5).await
+    })
+    .await;
+}
+
+/// Small segments fill up after a few records and old ones age out: the log must roll over
+/// on both, leave sealed segments fully synced, and keep every sealed record across a crash.
+#[tokio::test]
+async fn segment_rotation() {
+    for_each_seed(|seed| async move {
+        let max_age = Duration::from_secs(10);
+        let rotation = RotationPolicy::default()
+            .with_max_segment_size(512)
+            .with_max_segment_age(max_age);
+        let workload = || {
+            PipelineWorkload::new("config_key")
+                .with_batching(BatchPolicy::default().with_max_records(1))
+                .with_rotation(rotation.clone())
+        };
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut pipeline = workload();
+        init(&mut io, &mut pipeline).await?;
+        steps(&mut io, &mut pipeline, 20).await?;
+
+        let segments = io.segment_snapshots();
+        if segments.len() < 2 {
+            return Err(format!(
+                "still {} segment after 20 records of 512 byte segments",
+                segments.len()
+            ));
+        }
+        if let Some(index) = segments[..segments.len() - 1]
+            .iter()
+            .position(|segment| segment.contents != segment.synced_contents)
+        {
+            return Err(format!("segment {} was sealed without being synced", index));
+        }
+        if record_count(&segments) != 20 {
+            return Err(format!(
+                "expected 20 records across {} segments, found {}",
+                segments.len(),
+                record_count(&segments)
+            ));
+        }
+        info!("20 records rolled over into {} segments", segments.len());
+
+        io.advance_clock(max_age);
+        step(&mut io, &mut pipeline).await?;
+        let rolled = io.segment_snapshots().len();
+        if rolled != segments.len() + 1 {
+            return Err(format!(
+                "expected the segment to roll over after {:?}, have {} segments instead of {}",
+                max_age,
+                rolled,
+                segments.len() + 1
+            ));
+        }
+
+        let sealed = io.segment_snapshots();
+        let sealed = record_count(&sealed[..sealed.len() - 1]);
+        io.crash();
+        let mut pipeline = workload();
+        init(&mut io, &mut pipeline).await?;
+        let recovered = io.segment_snapshots();
+        if record_count(&recovered[..recovered.len() - 1]) != sealed {
+            return Err(format!(
+                "sealed segments held {} records before the crash and {} after",
+                sealed,
+                record_count(&recovered[..recovered.len() - 1])
+            ));
+        }
+        steps(&mut io, &mut pipeline, 5).await
+    })
+    .await;
+}
+
+/// Checks that the disk accounts for exactly the bytes held by the log's segments.
+fn check_disk_usage(io: &SimulatedIO) -> Result<(), String> {
+    let segments: usize = io
+        .segment_snapshots()
+        .iter()
+        .map(|segment| segment.contents.len())
+        .sum();
+    if io.disk().used() != segments {
+        return Err(format!(
+            "disk reports {} bytes used but the segments hold {}",
+            io.disk().used(),
+            segments
+        ));
+    }
+    Ok(())
+}
+
+/// The disk fills up: the pipeline must pause consumption instead of queueing records in
+/// memory, resume once space is freed, and give space back when segments are truncated or
+/// deleted.
+#[tokio::test]
+async fn disk_full() {
+    for_each_seed(|seed| async move {
+        let mut io = SimulatedIO::without_faults(seed);
+        let mut workload = PipelineWorkload::new("config_key")
+            .with_batching(BatchPolicy::default().with_max_records(1));
+        init(&mut io, &mut workload).await?;
+        steps(&mut io, &mut workload, 5).await?;
+
+        let capacity = io.disk().capacity();
+        io.disk().set_capacity(io.disk().used() + 200);
+        for _ in 0..10 {
+            step(&mut io, &mut workload).await?;
+            if workload.disk_full() {
+                break;
+            }
+        }
+        if !workload.disk_full() {
+            return Err(format!(
+                "disk never filled up, {} of {} bytes used",
+                io.disk().used(),
+                io.disk().capacity()
+            ));
+        }
+        let (queued, used) = (workload.queued_writes(), io.disk().used());
+        steps(&mut io, &mut workload, 5).await?;
+        if workload.queued_writes() != queued || io.disk().used() != used {
+            return Err(format!(
+                "kept consuming with the disk full: {} queued records became {}",
+                queued,
+                workload.queued_writes()
+            ));
+        }
+        info!("paused with {} records queued at {:?}", queued, io.now());
+
+        io.disk().set_capacity(capacity);
+        steps(&mut io, &mut workload, 5).await?;
+        if workload.disk_full() || workload.queued_writes() != 0 {
+            return Err(format!(
+                "still paused after space was freed, {} records queued",
+                workload.queued_writes()
+            ));
+        }
+
+        io.crash();
+        let mut workload = PipelineWorkload::new("config_key");
+        init(&mut io, &mut workload).await?;
+        check_disk_usage(&io)?;
+
+        //  Deleting old segments must free enough space to keep going on a disk that could
+        //  never hold every record
+        let mut io = SimulatedIO::without_faults(seed);
+        io.disk().set_capacity(1200);
+        let rotation = RotationPolicy::default()
+            .with_max_segment_size(512)
+            .with_max_segments(2);
+        let mut workload = PipelineWorkload::new("config_key")
+            .with_batching(BatchPolicy::default().with_max_records(1))
+            .with_rotation(rotation);
+        init(&mut io, &mut workload).await?;
+        for _ in 0..40 {
+            step(&mut io, &mut workload).await?;
+            if workload.disk_full() {
+                return Err(format!(
+                    "disk filled up despite segment retention, {} bytes used",
+                    io.disk().used()
+                ));
+            }
+        }
+        check_disk_usage(&io)
+    })
+    .await;
+}
+
+/// Files in the simulated filesystem can be created in nested directories, renamed, listed
+/// and deleted, with the disk accounting following along.
+#[tokio::test]
+async fn file_system() {
+    for_each_seed(|seed| async move {
+        let mut io = SimulatedIO::without_faults(seed);
+        let fs = io.file_system().clone();
+        let fail = |e: Errors| e.to_string();
+
+        let directory = Path::new("spill/pending");
+        fs.create_dir_all(directory);
+        let mut file = io
+            .open_simulated_file(&directory.join("a.tmp"))
+            .await
+            .map_err(fail)?;
+        file.write("first record").await.map_err(fail)?;
+        file.fsync().await.map_err(fail)?;
+
+        fs.rename(&directory.join("a.tmp"), &directory.join("a.log"))
+            .map_err(fail)?;
+        let listed = fs.list(directory).map_err(fail)?;
+        if listed != vec![PathBuf::from("spill/pending/a.log")] {
+            return Err(format!(
+                "expected only a.log after the rename, found {:?}",
+                listed
+            ));
+        }
+        let mut renamed = io
+            .open_simulated_file(&directory.join("a.log"))
+            .await
+            .map_err(fail)?;
+        let entries = renamed.read_last_n_entries(1).await.map_err(fail)?;
+        if entries != vec!["first record".to_string()] {
+            return Err(format!("renamed file holds {:?}", entries));
+        }
+
+        fs.unlink(&directory.join("a.log")).map_err(fail)?;
+        if fs.exists(&directory.join("a.log")) || io.disk().used() != 0 {
+            return Err(format!(
+                "deleted file still around, {} bytes in use",
+                io.disk().used()
+            ));
+        }
+        if fs.list(Path::new("missing")).is_ok() {
+            return Err("listed a directory that was never created".to_string());
+        }
+        Ok(())
+    })
+    .await;
+}
+
+/// Writes `contents` to a temporary file next to `path`, syncs it and renames it over
+/// `path`, syncing the directory too when `sync_dir` is set.
+async fn replace_file(
+    io: &mut SimulatedIO,
+    path: &Path,
+    contents: &str,
+    sync_dir: bool,
+) -> Result<(), Errors> {
+    let temporary = path.with_extension("tmp");
+    let mut file = io.open_simulated_file(&temporary).await?;
+    file.write(contents).await?;
+    file.fsync().await?;
+    io.rename_file(&temporary, path).await?;
+    if sync_dir {
+        io.sync_dir(path.parent().unwrap()).await?;
+    }
+    Ok(())
+}
+
+/// Every intact record of the file at `path`.
+async fn read_entries(fs: &SimulatedFileSystem, path: &Path) -> Result<Vec<String>, String> {
+    let mut file = SimulatedFile::open(ChaCha8Rng::seed_from_u64(0), fs.clone(), path)
+        .map_err(|e| e.to_string())?;
+    file.read_last_n_entries(usize::MAX)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// A crash that loses directory metadata: a rename whose directory was never synced is
+/// undone, along with the temporary file it renamed, while a synced one survives. Replacing
+/// or deleting a file without syncing its directory brings the old file back intact. The
+/// output log syncs its directory itself, so it comes through intact.
+#[tokio::test]
+async fn atomic_rename() {
+    for_each_seed(|seed| async move {
+        let mut io = SimulatedIO::without_faults(seed);
+        io.set_fault_probability(
+            FaultType::FileFaultType(FileFaultType::FileMetadataSyncFailure),
+            1.0,
+        );
+        let fs = io.file_system().clone();
+        let directory = Path::new("state");
+        let path = directory.join("config");
+        fs.create_dir_all(directory);
+
+        replace_file(&mut io, &path, "unsynced", false)
+            .await
+            .map_err(|e| format!("replacing without a directory sync failed: {}", e))?;
+        io.crash();
+        let listed = fs.list(directory).map_err(|e| e.to_string())?;
+        if !listed.is_empty() {
+            return Err(format!(
+                "unsynced rename survived a metadata losing crash: {:?}",
+                listed
+            ));
+        }
+
+        replace_file(&mut io, &path, "synced", true)
+            .await
+            .map_err(|e| format!("replacing with a directory sync failed: {}", e))?;
+        io.crash();
+        if fs.list(directory).map_err(|e| e.to_string())? != vec![path.clone()] {
+            return Err(format!(
+                "synced rename was lost, {} is missing",
+                path.display()
+            ));
+        }
+        let entries = read_entries(&fs, &path).await?;
+        if entries != vec!["synced".to_string()] {
+            return Err(format!("renamed file holds {:?} after the crash", entries));
+        }
+
+        replace_file(&mut io, &path, "replacement", false)
+            .await
+            .map_err(|e| format!("replacing over an existing file failed: {}", e))?;
+        io.crash();
+        let listed = fs.list(directory).map_err(|e| e.to_string())?;
+        if listed != vec![path.clone()] {
+            return Err(format!(
+                "unsynced rename over {} left {:?} after the crash",
+                path.display(),
+                listed
+            ));
+        }
+        let entries = read_entries(&fs, &path).await?;
+        if entries != vec!["synced".to_string()] {
+            return Err(format!(
+                "replaced file holds {:?} after the rename was undone",
+                entries
+            ));
+        }
+
+        io.remove_file(&path)
+            .await
+            .map_err(|e| format!("deleting {} failed: {}", path.display(), e))?;
+        io.crash();
+        let entries = read_entries(&fs, &path).await?;
+        if entries != vec!["synced".to_string()] {
+            return Err(format!(
+                "deleted file holds {:?} after the delete was undone",
+                entries
+            ));
+        }
+
+        let mut workload = PipelineWorkload::new("config_key")
+            .with_batching(BatchPolicy::default().with_max_records(1));
+        init(&mut io, &mut workload).await?;
+        steps(&mut io, &mut workload, 5).await?;
+        io.sync_file()
+            .await
+            .map_err(|e| format!("fsync failed: {}", e))?;
+        io.crash();
+        let mut workload = PipelineWorkload::new("config_key");
+        init(&mut io, &mut workload).await?;
+        let records = record_count(&io.segment_snapshots());
+        if records != 5 {
+            return Err(format!(
+                "expected the 5 synced records to survive, found {}",
+                records
+            ));
+        }
+        Ok(())
+    })
+    .await;
+}